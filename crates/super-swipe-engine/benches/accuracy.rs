@@ -0,0 +1,277 @@
+//! Accuracy and latency of the engine on synthetic swipes.
+//!
+//! Swipes are generated from the key centres of each word with some jitter, once starting on
+//! the right key and once slipping onto a neighbouring one, and matched with each start radius
+//! and distance metric. The share of candidates the lower bounds skip is reported with each run,
+//! and once more with the test words hidden among `SWIPE_BENCH_FILLERS` (default 200000) random
+//! letter strings, as in a large dictionary, for each search strategy, and with approximate retrieval
+//! along with its recall of the exhaustive predictions, and with sessions fed a point at a time.
+//! Set `SWIPE_BENCH_MODEL` to an English model file to match against a real dictionary;
+//! otherwise only the test words are used.
+//!
+//! ```sh
+//! cargo bench -p super-swipe-engine --bench accuracy
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use super_swipe_engine::{keyboard_layout, BlendComponent, DistanceMetric, EngineConfig, Retrieval, SearchStats, SearchStrategy, Shark2, SwipeEngine};
+use swipe_types::model::{encode_model, FrequencySource};
+use swipe_types::types::{Dictionary, Point, WordInfo};
+
+const WORDS: &str = "the of and to in is you that it he was for on are as with his they at be this have from \
+    or one had by word but not what all were we when your can said there use an each which she do how their \
+    if will up other about out many then them these so some her would make like him into time has look two \
+    more write go see number no way could people my than first water been call who oil its now find long down \
+    day did get come made may part over new sound take only little work know place year live me back give most \
+    very after thing our just name good sentence man think say great where help through much before line right \
+    too mean old any same tell boy follow came want show also around form three small set put end does another \
+    well large must big even such because turn here why ask went men read need land different home us move try \
+    kind hand picture again change off play spell air away animal house point page letter mother answer found \
+    study still learn should world high every near add food between own below country plant last school father \
+    keep tree never start city earth eye light thought head under story saw left few while along might close \
+    something seem next hard open example begin life always those both paper together got group often run";
+
+/// Number of test swipes per word and slip setting.
+const SWIPES_PER_WORD: usize = 3;
+/// Standard deviation of the jitter added to each key point, in key units.
+const JITTER: f64 = 0.2;
+/// How far a slipped swipe starts from the centre of its first key, in key units.
+const SLIP: f64 = 0.8;
+
+struct Case {
+    word: String,
+    points: Vec<Point>,
+}
+
+#[derive(Default)]
+struct Report {
+    top1: usize,
+    top3: usize,
+    total: usize,
+    latencies: Vec<Duration>,
+    stats: SearchStats,
+    /// The predicted words of each case.
+    predictions: Vec<Vec<String>>,
+}
+
+impl Report {
+    fn print(&mut self, label: &str) {
+        self.latencies.sort();
+        let mean = self.latencies.iter().sum::<Duration>() / self.latencies.len().max(1) as u32;
+        let p95 = self.latencies.get(self.latencies.len() * 95 / 100).copied().unwrap_or_default();
+        println!(
+            "{:<28} top1 {:5.1}%  top3 {:5.1}%  mean {:>8.1?}  p95 {:>8.1?}  candidates {:>7.1}  skipped {:5.1}%",
+            label,
+            100.0 * self.top1 as f64 / self.total as f64,
+            100.0 * self.top3 as f64 / self.total as f64,
+            mean,
+            p95,
+            self.stats.candidates as f64 / self.total as f64,
+            100.0 * self.stats.reduction(),
+        );
+    }
+
+    /// Share of the words predicted in `reference` that were predicted here too.
+    fn recall(&self, reference: &Report) -> f64 {
+        let (mut found, mut total) = (0, 0);
+        for (words, expected) in self.predictions.iter().zip(&reference.predictions) {
+            found += expected.iter().filter(|word| words.contains(word)).count();
+            total += expected.len();
+        }
+        found as f64 / total.max(1) as f64
+    }
+}
+
+/// Deterministic xorshift generator, so runs are comparable.
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal sample, with the Box-Muller transform.
+    fn gaussian(&mut self) -> f64 {
+        let u = self.next_f64().max(f64::MIN_POSITIVE);
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+}
+
+fn dictionary(fillers: usize) -> Dictionary {
+    let mut dictionary = Dictionary::new();
+    let words: Vec<&str> = WORDS.split_whitespace().collect();
+    for (rank, word) in words.iter().enumerate() {
+        // Zipf's law, scaled to the 0..1 range of real models
+        let count = (100_000 / (rank + 1)) as u32;
+        let log_freq = (count as f64).ln() / 100_000f64.ln();
+        dictionary.words.push(word.to_string());
+        dictionary.word_info.insert(word.to_string(), WordInfo { log_freq, count });
+    }
+
+    // rare made-up words, with common letters more likely
+    let letters: Vec<char> = "etaoinshrdlcumwfgypbvkjxqz".chars().collect();
+    let mut rng = Rng(0x0f11_1e75);
+    while dictionary.words.len() < words.len() + fillers {
+        let len = 3 + (rng.next_f64() * 8.0) as usize;
+        let word: String = (0..len).map(|_| letters[(rng.next_f64().powi(2) * letters.len() as f64) as usize]).collect();
+        if !dictionary.word_info.contains_key(&word) {
+            dictionary.word_info.insert(word.clone(), WordInfo { log_freq: 0.0, count: 1 });
+            dictionary.words.push(word);
+        }
+    }
+    dictionary
+}
+
+fn model_bytes() -> Vec<u8> {
+    match std::env::var("SWIPE_BENCH_MODEL") {
+        Ok(path) => std::fs::read(Path::new(&path)).expect("SWIPE_BENCH_MODEL is not readable"),
+        Err(_) => encode_model("en", FrequencySource::Corpus, &dictionary(0)),
+    }
+}
+
+/// A jittered swipe through the keys of `word`, with points every 0.1 key units.
+fn swipe(word: &str, layout: &HashMap<char, Point>, rng: &mut Rng, slip: bool) -> Option<Vec<Point>> {
+    let mut keys: Vec<Point> = word
+        .chars()
+        .map(|c| layout.get(&c).map(|p| Point { x: p.x + JITTER * rng.gaussian(), y: p.y + JITTER * rng.gaussian() }))
+        .collect::<Option<_>>()?;
+    if slip {
+        let angle = 2.0 * std::f64::consts::PI * rng.next_f64();
+        keys[0] = Point { x: keys[0].x + SLIP * angle.cos(), y: keys[0].y + SLIP * angle.sin() };
+    }
+
+    let mut points = vec![keys[0]];
+    for pair in keys.windows(2) {
+        let steps = ((pair[1].x - pair[0].x).hypot(pair[1].y - pair[0].y) / 0.1).ceil().max(1.0) as usize;
+        for s in 1..=steps {
+            let t = s as f64 / steps as f64;
+            points.push(Point { x: pair[0].x + (pair[1].x - pair[0].x) * t, y: pair[0].y + (pair[1].y - pair[0].y) * t });
+        }
+    }
+    Some(points)
+}
+
+fn cases(words: &[String], slip: bool) -> Vec<Case> {
+    let layout = keyboard_layout();
+    let mut rng = Rng(0x5eed_cafe);
+    words
+        .iter()
+        .filter(|word| word.chars().count() >= 2)
+        .flat_map(|word| (0..SWIPES_PER_WORD).map(|_| (word.clone(), swipe(&word.to_lowercase(), &layout, &mut rng, slip))).collect::<Vec<_>>())
+        .filter_map(|(word, points)| Some(Case { word, points: points? }))
+        .collect()
+}
+
+fn evaluate(engine: &SwipeEngine, cases: &[Case]) -> Report {
+    let mut report = Report::default();
+    for case in cases {
+        let start = Instant::now();
+        let (predictions, stats) = engine.predict_path_with_stats(&case.points, None, 3);
+        report.latencies.push(start.elapsed());
+        report.stats += stats;
+        report.total += 1;
+        let rank = predictions.iter().position(|p| p.word == case.word);
+        report.top1 += (rank == Some(0)) as usize;
+        report.top3 += rank.is_some() as usize;
+        report.predictions.push(predictions.into_iter().map(|p| p.word).collect());
+    }
+    report
+}
+
+/// Like [`evaluate`], feeding each swipe to a session a point at a time.
+fn evaluate_session(engine: &SwipeEngine, cases: &[Case]) -> Report {
+    let mut report = Report::default();
+    for case in cases {
+        let start = Instant::now();
+        let mut session = engine.session(None);
+        for point in &case.points {
+            session.push_point(*point);
+        }
+        let predictions = session.finish(3);
+        report.latencies.push(start.elapsed());
+        report.total += 1;
+        let rank = predictions.iter().position(|p| p.word == case.word);
+        report.top1 += (rank == Some(0)) as usize;
+        report.top3 += rank.is_some() as usize;
+        report.predictions.push(predictions.into_iter().map(|p| p.word).collect());
+    }
+    report
+}
+
+fn main() {
+    let bytes = model_bytes();
+    let engine = SwipeEngine::from_bytes(&bytes, None).expect("invalid model");
+    // the built-in words are common enough to be in any English model
+    let words: Vec<String> = WORDS.split_whitespace().map(str::to_string).collect();
+    println!("{} words in the model, {} test words", engine.word_count(), words.len());
+
+    let build = |config: EngineConfig| SwipeEngine::builder().model_bytes(bytes.clone()).config(config).build().expect("invalid config");
+
+    for (slip, name) in [(false, "on key"), (true, "slipped")] {
+        let cases = cases(&words, slip);
+        for start_radius in [0.0, 0.75, 1.25] {
+            let engine = build(EngineConfig { start_radius, ..EngineConfig::default() });
+            evaluate(&engine, &cases).print(&format!("{} start_radius {}", name, start_radius));
+        }
+    }
+
+    println!();
+    let cases = cases(&words, false);
+    let metrics = [
+        ("dtw", DistanceMetric::Dtw),
+        ("derivative dtw", DistanceMetric::DerivativeDtw),
+        ("frechet", DistanceMetric::Frechet),
+        ("shark2", DistanceMetric::Shark2(Shark2::default())),
+        (
+            "dtw + shark2",
+            DistanceMetric::Blend {
+                components: vec![
+                    BlendComponent { metric: DistanceMetric::Dtw, weight: 1.0 },
+                    BlendComponent { metric: DistanceMetric::Shark2(Shark2::default()), weight: 1.0 },
+                ],
+            },
+        ),
+    ];
+    for (name, distance) in metrics {
+        let engine = build(EngineConfig { distance, ..EngineConfig::default() });
+        evaluate(&engine, &cases).print(name);
+    }
+
+    println!();
+    let fillers = std::env::var("SWIPE_BENCH_FILLERS").ok().and_then(|n| n.parse().ok()).unwrap_or(200_000);
+    let large = encode_model("en", FrequencySource::Corpus, &dictionary(fillers));
+    let mut exhaustive = None;
+    for search in [SearchStrategy::Linear, SearchStrategy::Trie] {
+        let start = Instant::now();
+        let engine = SwipeEngine::builder().model_bytes(large.clone()).search(search).build().expect("invalid model");
+        let load = start.elapsed();
+        let mut report = evaluate(&engine, &cases);
+        report.print(&format!("{} words, {:?}", engine.word_count(), search));
+        println!("{:<28} loaded in {:.1?}, {} distinct paths", "", load, engine.path_count());
+        exhaustive.get_or_insert(report);
+    }
+    let exhaustive = exhaustive.expect("no exhaustive run");
+
+    for candidates in [100, 300, 1000] {
+        let retrieval = Retrieval::Ann { candidates, points: 16 };
+        let start = Instant::now();
+        let engine = SwipeEngine::builder().model_bytes(large.clone()).retrieval(retrieval).build().expect("invalid model");
+        let load = start.elapsed();
+        let mut report = evaluate(&engine, &cases);
+        report.print(&format!("ann, {} candidates", candidates));
+        println!("{:<28} loaded in {:.1?}, recall {:.1}%", "", load, 100.0 * report.recall(&exhaustive));
+    }
+
+    // sessions prune candidates heuristically as the swipe goes on
+    let engine = SwipeEngine::from_bytes(&large, None).expect("invalid model");
+    let mut report = evaluate_session(&engine, &cases);
+    report.print("session");
+    let same = report.predictions.iter().zip(&exhaustive.predictions).filter(|(words, expected)| words == expected).count();
+    println!("{:<28} recall {:.1}%, same top 3 on {} of {} swipes", "", 100.0 * report.recall(&exhaustive), same, report.total);
+}
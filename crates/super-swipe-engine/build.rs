@@ -1,11 +1,16 @@
-use std::collections::{HashMap, HashSet};
+#[cfg(any(feature = "use-corpus", feature = "use-word-frequency-files"))]
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::{env, fs};
+#[cfg(feature = "use-corpus")]
 use std::fs::File;
+#[cfg(feature = "use-corpus")]
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
+#[cfg(any(feature = "use-corpus", feature = "use-word-frequency-files"))]
+use swipe_types::model::{encode_model, FrequencySource};
+#[cfg(any(feature = "use-corpus", feature = "use-word-frequency-files"))]
 use swipe_types::types::{Dictionary, WordInfo};
-use bincode;
-use bincode::config;
 use codes_iso_639::part_1::LanguageCode;
 
 
@@ -16,10 +21,18 @@ fn main() {
     if !lang_data_bin_dir.exists() {
         fs::create_dir_all(&lang_data_bin_dir).unwrap()
     }
+    compile_language_models(&manifest_dir, &lang_data_bin_dir);
+
+    if env::var_os("CARGO_FEATURE_EMBEDDED_MODELS").is_some() {
+        write_embedded_models(&lang_data_bin_dir);
+    }
+}
+
+fn compile_language_models(manifest_dir: &str, lang_data_bin_dir: &Path) {
     let
         lang_data_text_dir = match env::var("LANGDATA_DIR") {
         Ok(val) => PathBuf::from(val),
-        Err(_) => Path::new(&manifest_dir).join("lang-data/plaintext"),
+        Err(_) => Path::new(manifest_dir).join("lang-data/plaintext"),
     };
     println!("cargo:rerun-if-changed={}", lang_data_text_dir.display());
 
@@ -34,9 +47,10 @@ fn main() {
 
         let dir = dir.unwrap();
         let dir_path = &dir.path();
-        if let Ok(_lang_code) = dir_path.file_name().unwrap().to_str().unwrap().parse::<LanguageCode>() {
+        let lang = dir_path.file_name().unwrap().to_str().unwrap();
+        if let Ok(_lang_code) = lang.parse::<LanguageCode>() {
 
-            let full_dest_file_name = format!("{}.bin", dir.file_name().to_str().unwrap());
+            let full_dest_file_name = format!("{}.bin", lang);
             let dest_path = lang_data_bin_dir.join(&full_dest_file_name);
 
             if !dest_path.exists() {
@@ -44,7 +58,7 @@ fn main() {
                 let mut corpus_path: Option<PathBuf> = None;
                 let mut word_freq_path: Option<PathBuf> = None;
 
-                for potential_file in fs::read_dir(&dir_path).unwrap() {
+                for potential_file in fs::read_dir(dir_path).unwrap() {
                     let file_path = potential_file.unwrap().path();
                     if file_path.is_dir() { continue; }
 
@@ -57,6 +71,10 @@ fn main() {
                         word_freq_path = Some(file_path)
                     }
                 }
+                #[cfg(not(feature = "use-word-frequency-files"))]
+                let _ = word_freq_path;
+                #[cfg(not(feature = "use-corpus"))]
+                let _ = (word_list_path, corpus_path);
                 #[cfg(feature = "use-word-frequency-files")]
                 if let Some(word_freq_path) = word_freq_path {
                     let mut valid_words: HashSet<String> = HashSet::new();
@@ -83,8 +101,8 @@ fn main() {
                         words: valid_words.iter().cloned().collect(),
                         word_info: freq
                     };
-                    let serialized_model = bincode::encode_to_vec(&model, config::standard()).unwrap();
-                    fs::write(&dest_path, serialized_model).expect(&format!("Failed to write {full_dest_file_name}"));
+                    let serialized_model = encode_model(lang, FrequencySource::WordFrequency, &model);
+                    fs::write(&dest_path, serialized_model).unwrap_or_else(|e| panic!("Failed to write {full_dest_file_name}: {e}"));
                 }
 
 
@@ -106,8 +124,8 @@ fn main() {
                         let corpus_reader = BufReader::new(corpus_file);
 
                         let model = create_dictionary_from_corpus(corpus_reader, valid_words, valid_words_lowercase);
-                        let serialized_model = bincode::encode_to_vec(&model, config::standard()).unwrap();
-                        fs::write(&dest_path, serialized_model).expect(&format!("Failed to write {full_dest_file_name}"));
+                        let serialized_model = encode_model(lang, FrequencySource::Corpus, &model);
+                        fs::write(&dest_path, serialized_model).unwrap_or_else(|e| panic!("Failed to write {full_dest_file_name}: {e}"));
                     }
                 }
             }
@@ -115,9 +133,56 @@ fn main() {
     }
 }
 
+/// Generates `embedded_models.rs` in `OUT_DIR`, mapping language codes to the
+/// compiled models in `assets/`. Set `SWIPE_EMBEDDED_LANGS` to a comma separated
+/// list of 639-1 codes to embed only some of them.
+fn write_embedded_models(lang_data_bin_dir: &Path) {
+    println!("cargo:rerun-if-env-changed=SWIPE_EMBEDDED_LANGS");
+    println!("cargo:rerun-if-changed={}", lang_data_bin_dir.display());
+
+    let selected_langs: Option<HashSet<String>> = env::var("SWIPE_EMBEDDED_LANGS").ok().map(|langs| {
+        langs.split(',').map(|lang| lang.trim().to_string()).filter(|lang| !lang.is_empty()).collect()
+    });
+
+    let mut match_arms = String::new();
+    let mut model_files: Vec<PathBuf> = fs::read_dir(lang_data_bin_dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bin"))
+        .collect();
+    model_files.sort();
+
+    for model_file in model_files {
+        let lang = model_file.file_stem().unwrap().to_str().unwrap().to_string();
+        if lang.parse::<LanguageCode>().is_err() {
+            continue;
+        }
+        if let Some(selected_langs) = &selected_langs {
+            if !selected_langs.contains(&lang) {
+                continue;
+            }
+        }
+        println!("cargo:rerun-if-changed={}", model_file.display());
+        match_arms.push_str(&format!("        {:?} => Some(include_bytes!({:?})),\n", lang, model_file.display().to_string()));
+    }
+
+    if match_arms.is_empty() {
+        println!("cargo:warning=embedded-models is enabled but no models were found in {}", lang_data_bin_dir.display());
+    }
+
+    let source = format!(
+        "/// Returns the compiled model embedded for a 639-1 language code.\n\
+        #[allow(clippy::match_single_binding)]\n\
+        pub(crate) fn embedded_model(lang_code: &str) -> Option<&'static [u8]> {{\n    match lang_code {{\n{}        _ => None,\n    }}\n}}\n",
+        match_arms
+    );
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("embedded_models.rs"), source).unwrap();
+}
 
+#[cfg(feature = "use-corpus")]
 fn create_dictionary_from_corpus(corpus_reader: BufReader<File>, valid_words: HashSet<String>, valid_words_lowercase: HashSet<String>) -> Dictionary
 {
+    #[cfg_attr(not(feature = "use-pair-counts"), allow(unused_mut))]
     let mut pair_counts: HashMap<String, HashMap<String, u32>> = HashMap::new();
     let mut word_count: HashMap<String, u32> = HashMap::new();
     let mut freq= HashMap::new();
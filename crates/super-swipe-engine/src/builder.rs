@@ -0,0 +1,236 @@
+use crate::distance::DistanceMetric;
+use crate::folding::Folding;
+use crate::key_hits::KeyHitParams;
+use crate::keyboard::{unit_key_rects, BuiltinLayout, KeyModel, KeyboardLayout, Layer};
+use crate::preprocess::PathPreprocessing;
+use crate::retrieval::Retrieval;
+use crate::search::SearchStrategy;
+use crate::unknown_chars::UnknownCharPolicy;
+use crate::{add_word, EngineConfig, EngineLoadError, ModelSource, SwipeEngine};
+use codes_iso_639::part_1::LanguageCode;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use swipe_types::types::Point;
+
+enum ModelInput {
+    Language(LanguageCode),
+    Source(LanguageCode, ModelSource),
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+enum LayoutInput {
+    Keys(HashMap<char, Point>),
+    Definition(KeyboardLayout),
+}
+
+/// Configures and loads a [`SwipeEngine`].
+///
+/// ```no_run
+/// use codes_iso_639::part_1::LanguageCode;
+/// use super_swipe_engine::SwipeEngine;
+///
+/// let engine = SwipeEngine::builder()
+///     .language(LanguageCode::En)
+///     .pop_weight(0.3)
+///     .end_penalty_factor(0.4)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Default)]
+pub struct SwipeEngineBuilder {
+    model: Option<ModelInput>,
+    layout: Option<LayoutInput>,
+    config: EngineConfig,
+    extra_words: Vec<(String, f64)>,
+}
+
+impl SwipeEngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the model for `lang_code` the same way as [`SwipeEngine::new`].
+    pub fn language(mut self, lang_code: LanguageCode) -> Self {
+        self.model = Some(ModelInput::Language(lang_code));
+        self
+    }
+
+    pub fn source(mut self, lang_code: LanguageCode, source: ModelSource) -> Self {
+        self.model = Some(ModelInput::Source(lang_code, source));
+        self
+    }
+
+    pub fn model_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.model = Some(ModelInput::Path(path.into()));
+        self
+    }
+
+    pub fn model_bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.model = Some(ModelInput::Bytes(bytes.into()));
+        self
+    }
+
+    pub fn layout(mut self, layout: HashMap<char, Point>) -> Self {
+        self.layout = Some(LayoutInput::Keys(layout));
+        self
+    }
+
+    /// Uses a layout definition, for example one read with [`KeyboardLayout::from_file`].
+    /// It is validated when the engine is built.
+    pub fn keyboard_layout(mut self, layout: KeyboardLayout) -> Self {
+        self.layout = Some(LayoutInput::Definition(layout));
+        self
+    }
+
+    pub fn builtin_layout(self, layout: BuiltinLayout) -> Self {
+        self.keyboard_layout(layout.definition())
+    }
+
+    /// Replaces all settings, for example with a stored configuration.
+    pub fn config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn pop_weight(mut self, weight: f64) -> Self {
+        self.config.pop_weight = weight;
+        self
+    }
+
+    pub fn bigram_weight(mut self, weight: f64) -> Self {
+        self.config.bigram_weight = weight;
+        self
+    }
+
+    pub fn window_divisor(mut self, divisor: usize) -> Self {
+        self.config.window_divisor = divisor;
+        self
+    }
+
+    pub fn min_window(mut self, min_window: usize) -> Self {
+        self.config.min_window = min_window;
+        self
+    }
+
+    pub fn end_penalty_factor(mut self, factor: f64) -> Self {
+        self.config.end_penalty_factor = factor;
+        self
+    }
+
+    pub fn unknown_key_penalty(mut self, penalty: f64) -> Self {
+        self.config.unknown_key_penalty = penalty;
+        self
+    }
+
+    pub fn resample_step(mut self, step: f64) -> Self {
+        self.config.resample_step = step;
+        self
+    }
+
+    pub fn simplify_threshold(mut self, threshold: f64) -> Self {
+        self.config.simplify_threshold = threshold;
+        self
+    }
+
+    pub fn preprocessing(mut self, preprocessing: PathPreprocessing) -> Self {
+        self.config.preprocessing = preprocessing;
+        self
+    }
+
+    pub fn key_model(mut self, model: KeyModel) -> Self {
+        self.config.key_model = model;
+        self
+    }
+
+    pub fn key_hit_weight(mut self, weight: f64) -> Self {
+        self.config.key_hit_weight = weight;
+        self
+    }
+
+    pub fn key_hit_params(mut self, params: KeyHitParams) -> Self {
+        self.config.key_hits = params;
+        self
+    }
+
+    pub fn double_letter_loop_radius(mut self, radius: f64) -> Self {
+        self.config.double_letter_loop_radius = radius;
+        self
+    }
+
+    pub fn double_letter_penalty(mut self, penalty: f64) -> Self {
+        self.config.double_letter_penalty = penalty;
+        self
+    }
+
+    pub fn folding(mut self, folding: Folding) -> Self {
+        self.config.folding = folding;
+        self
+    }
+
+    pub fn unknown_chars(mut self, policy: UnknownCharPolicy) -> Self {
+        self.config.unknown_chars = policy;
+        self
+    }
+
+    pub fn session_prune_margin(mut self, margin: f64) -> Self {
+        self.config.session_prune_margin = margin;
+        self
+    }
+
+    pub fn distance(mut self, distance: DistanceMetric) -> Self {
+        self.config.distance = distance;
+        self
+    }
+
+    pub fn search(mut self, search: SearchStrategy) -> Self {
+        self.config.search = search;
+        self
+    }
+
+    pub fn retrieval(mut self, retrieval: Retrieval) -> Self {
+        self.config.retrieval = retrieval;
+        self
+    }
+
+    pub fn template_tolerance(mut self, tolerance: f64) -> Self {
+        self.config.template_tolerance = tolerance;
+        self
+    }
+
+    pub fn start_radius(mut self, radius: f64) -> Self {
+        self.config.start_radius = radius;
+        self
+    }
+
+    pub fn start_penalty_factor(mut self, factor: f64) -> Self {
+        self.config.start_penalty_factor = factor;
+        self
+    }
+
+    /// Adds words the model doesn't have, see [`SwipeEngine::add_words`].
+    pub fn extra_words(mut self, words: &[&str], log_freq: f64) -> Self {
+        self.extra_words.extend(words.iter().map(|word| (word.to_string(), log_freq)));
+        self
+    }
+
+    pub fn build(self) -> Result<SwipeEngine, EngineLoadError> {
+        self.config.validate()?;
+        let (header, mut dictionary) = match self.model {
+            Some(ModelInput::Language(lang_code)) => SwipeEngine::load_language(lang_code)?,
+            Some(ModelInput::Source(lang_code, source)) => SwipeEngine::load_source(lang_code, &source)?,
+            Some(ModelInput::Path(path)) => SwipeEngine::load_path(&path)?,
+            Some(ModelInput::Bytes(bytes)) => SwipeEngine::load_bytes(&bytes)?,
+            None => return Err(EngineLoadError::InvalidConfig("no model was given to the builder".to_string())),
+        };
+        let layers = match self.layout {
+            Some(LayoutInput::Keys(layout)) => Some(vec![Layer::base(unit_key_rects(&layout))]),
+            Some(LayoutInput::Definition(layout)) => Some(layout.layer_keys()?),
+            None => None,
+        };
+        for (word, log_freq) in &self.extra_words {
+            add_word(&mut dictionary, word, *log_freq);
+        }
+        SwipeEngine::from_dictionary(header, dictionary, layers, self.config)
+    }
+}
@@ -0,0 +1,156 @@
+use crate::distance::DistanceMetric;
+use crate::folding::Folding;
+use crate::key_hits::KeyHitParams;
+use crate::keyboard::KeyModel;
+use crate::preprocess::PathPreprocessing;
+use crate::retrieval::Retrieval;
+use crate::search::SearchStrategy;
+use crate::unknown_chars::UnknownCharPolicy;
+use crate::EngineLoadError;
+use serde::{Deserialize, Serialize};
+
+/// Tunable parameters of a [`crate::SwipeEngine`].
+///
+/// Missing fields take their default value when deserializing, so stored
+/// configurations keep working when new settings are added.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    /// Higher values favor common words more heavily in the scoring function.
+    pub pop_weight: f64,
+    /// Higher values favor words that are more likely to follow the previous word.
+    pub bigram_weight: f64,
+    /// The DTW band is the input path length divided by this value.
+    pub window_divisor: usize,
+    /// Lower limit of the DTW band, in path points.
+    pub min_window: usize,
+    /// Scales the distance between the last key of the input and the last key of a word.
+    pub end_penalty_factor: f64,
+    /// End penalty for words without any key on the layout.
+    pub unknown_key_penalty: f64,
+    /// Distance between the points interpolated along a path, in key units.
+    pub resample_step: f64,
+    /// Consecutive path points closer than this are merged, in key units.
+    pub simplify_threshold: f64,
+    /// Applied to input and word paths after simplification.
+    pub preprocessing: PathPreprocessing,
+    /// Cost of an input point against a word path point.
+    pub key_model: KeyModel,
+    /// Penalty per key unit between a key hit detected in a trace and the closest key of a word,
+    /// scaled by the hit's confidence.
+    pub key_hit_weight: f64,
+    pub key_hits: KeyHitParams,
+    /// Radius of the loop drawn on repeated keys in word paths and key inputs, in key units.
+    /// 0 collapses repeated keys, so "too" and "to" get the same path.
+    pub double_letter_loop_radius: f64,
+    /// Penalty for each repeated key of a key input that a word doesn't repeat, and the other
+    /// way round. Raw traces are only compared by their shape.
+    pub double_letter_penalty: f64,
+    /// How accented letters that aren't on the layout are matched.
+    pub folding: Folding,
+    /// How characters that aren't on the layout, even after folding, are typed.
+    pub unknown_chars: UnknownCharPolicy,
+    /// Distance between the input and word paths that words are ranked by.
+    pub distance: DistanceMetric,
+    /// How candidates are searched. Both strategies return the same predictions.
+    pub search: SearchStrategy,
+    /// Which paths are scored at all: every one starting near the swipe, or only the nearest
+    /// ones by their embedding.
+    pub retrieval: Retrieval,
+    /// Word paths whose points fall in the same cells of a grid this size, in key units, share
+    /// one template and are scored with the same distance. 0 only shares identical paths, which
+    /// leaves every score unchanged.
+    pub template_tolerance: f64,
+    /// Words starting on keys within this distance of the start of a swipe are candidates too,
+    /// not just those starting on the closest key. 0 only considers the closest key.
+    pub start_radius: f64,
+    /// Like `end_penalty_factor`, for words starting on another key than the input.
+    pub start_penalty_factor: f64,
+    /// A [`crate::SwipeSession`] drops words whose cheapest partial alignment costs more than
+    /// this much per input point over the best word's.
+    pub session_prune_margin: f64,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            pop_weight: 0.25,
+            bigram_weight: 0.5,
+            window_divisor: 2,
+            min_window: 10,
+            end_penalty_factor: 0.5,
+            unknown_key_penalty: 50.0,
+            resample_step: 0.5,
+            simplify_threshold: 0.01,
+            preprocessing: PathPreprocessing::Step,
+            key_model: KeyModel::Centre,
+            key_hit_weight: 0.5,
+            key_hits: KeyHitParams::default(),
+            double_letter_loop_radius: 0.25,
+            double_letter_penalty: 1.0,
+            folding: Folding::Auto,
+            unknown_chars: UnknownCharPolicy::Skip,
+            session_prune_margin: 1.5,
+            distance: DistanceMetric::Dtw,
+            search: SearchStrategy::Linear,
+            retrieval: Retrieval::Exhaustive,
+            template_tolerance: 0.0,
+            start_radius: 0.0,
+            start_penalty_factor: 0.5,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn validate(&self) -> Result<(), EngineLoadError> {
+        let non_negative = [
+            ("pop_weight", self.pop_weight),
+            ("bigram_weight", self.bigram_weight),
+            ("end_penalty_factor", self.end_penalty_factor),
+            ("unknown_key_penalty", self.unknown_key_penalty),
+            ("simplify_threshold", self.simplify_threshold),
+            ("key_hit_weight", self.key_hit_weight),
+            ("key_hits.dwell_speed_ratio", self.key_hits.dwell_speed_ratio),
+            ("key_hits.corner_angle", self.key_hits.corner_angle),
+            ("key_hits.corner_span", self.key_hits.corner_span),
+            ("double_letter_loop_radius", self.double_letter_loop_radius),
+            ("double_letter_penalty", self.double_letter_penalty),
+            ("session_prune_margin", self.session_prune_margin),
+            ("start_radius", self.start_radius),
+            ("start_penalty_factor", self.start_penalty_factor),
+            ("template_tolerance", self.template_tolerance),
+        ];
+        for (name, value) in non_negative {
+            if !value.is_finite() || value < 0.0 {
+                return Err(EngineLoadError::InvalidConfig(format!("{} must be a finite, non-negative number, got {}", name, value)));
+            }
+        }
+        if !self.resample_step.is_finite() || self.resample_step <= 0.0 {
+            return Err(EngineLoadError::InvalidConfig(format!("resample_step must be positive, got {}", self.resample_step)));
+        }
+        if let PathPreprocessing::Uniform { points, .. } = self.preprocessing {
+            if points < 2 {
+                return Err(EngineLoadError::InvalidConfig(format!("uniform preprocessing needs at least 2 points, got {}", points)));
+            }
+        }
+        if let KeyModel::Gaussian { sigma } = self.key_model {
+            if !sigma.is_finite() || sigma <= 0.0 {
+                return Err(EngineLoadError::InvalidConfig(format!("gaussian key model sigma must be positive, got {}", sigma)));
+            }
+            if let PathPreprocessing::Uniform { normalize: true, .. } = self.preprocessing {
+                return Err(EngineLoadError::InvalidConfig("the gaussian key model needs paths in layout coordinates, not normalized ones".to_string()));
+            }
+        }
+        self.distance.validate().map_err(EngineLoadError::InvalidConfig)?;
+        self.retrieval.validate().map_err(EngineLoadError::InvalidConfig)?;
+        if self.window_divisor == 0 {
+            return Err(EngineLoadError::InvalidConfig("window_divisor must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Width of the DTW band for an input path of `input_len` points.
+    pub fn window(&self, input_len: usize) -> usize {
+        (input_len / self.window_divisor).max(self.min_window)
+    }
+}
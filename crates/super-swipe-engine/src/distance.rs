@@ -0,0 +1,209 @@
+use crate::dtw::{dtw_banded_with, dtw_distance_fast};
+use crate::keyboard::euclidean_dist;
+use crate::preprocess::{normalize_path, resample_uniform};
+use serde::{Deserialize, Serialize};
+use swipe_types::types::Point;
+
+/// A distance between an input path and a word path.
+///
+/// Distances are on the scale of a DTW sum: roughly the input length times the typical
+/// distance between matched points, so they can be added to the end penalties and divided
+/// by the input length like the default metric.
+pub trait PathDistance {
+    /// Distance between `input` and `template`. `window` is the widest shift between matched
+    /// points for metrics that warp the paths. Implementations may return infinity as soon as
+    /// the distance is known to exceed `cutoff`.
+    fn distance(&self, input: &[Point], template: &[Point], window: usize, cutoff: f64) -> f64;
+}
+
+/// Banded DTW with Euclidean point costs, the engine's original metric.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dtw;
+
+impl PathDistance for Dtw {
+    fn distance(&self, input: &[Point], template: &[Point], window: usize, cutoff: f64) -> f64 {
+        dtw_distance_fast(input, template, window, cutoff)
+    }
+}
+
+/// DTW over the local direction of the paths rather than their positions (Keogh and Pazzani's
+/// derivative DTW), so a swipe drawn with the right shape but offset from the keys still matches.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DerivativeDtw;
+
+impl PathDistance for DerivativeDtw {
+    fn distance(&self, input: &[Point], template: &[Point], window: usize, cutoff: f64) -> f64 {
+        let (s, t) = (derivative(input), derivative(template));
+        dtw_banded_with(s.len(), t.len(), window, cutoff, |i, j| euclidean_dist(&s[i], &t[j]))
+    }
+}
+
+/// Estimated derivative of each point of `path`: the average of the slope to the previous point
+/// and the slope between its neighbours. The end points take the value of their neighbour.
+pub fn derivative(path: &[Point]) -> Vec<Point> {
+    if path.len() < 3 {
+        return vec![Point { x: 0.0, y: 0.0 }; path.len()];
+    }
+    let mut derivatives: Vec<Point> = path
+        .windows(3)
+        .map(|w| Point {
+            x: ((w[1].x - w[0].x) + (w[2].x - w[0].x) / 2.0) / 2.0,
+            y: ((w[1].y - w[0].y) + (w[2].y - w[0].y) / 2.0) / 2.0,
+        })
+        .collect();
+    derivatives.insert(0, derivatives[0]);
+    derivatives.push(derivatives[derivatives.len() - 1]);
+    derivatives
+}
+
+/// Discrete Fréchet distance: the largest gap between matched points under the best monotone
+/// matching, within the same band as DTW. It is scaled by the input length to compare with DTW sums.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DiscreteFrechet;
+
+impl PathDistance for DiscreteFrechet {
+    fn distance(&self, input: &[Point], template: &[Point], window: usize, cutoff: f64) -> f64 {
+        let scale = input.len() as f64;
+        frechet_banded(input, template, window, cutoff / scale) * scale
+    }
+}
+
+fn frechet_banded(s: &[Point], t: &[Point], window: usize, cutoff: f64) -> f64 {
+    let (n, m) = (s.len(), t.len());
+    if n == 0 || m == 0 || n.abs_diff(m) > window {
+        return f64::INFINITY;
+    }
+
+    let mut prev = vec![f64::INFINITY; m];
+    let mut curr = vec![f64::INFINITY; m];
+    for (i, point) in s.iter().enumerate() {
+        curr.fill(f64::INFINITY);
+        let j_start = i.saturating_sub(window);
+        let j_end = (i + window).min(m - 1);
+        let mut row_min = f64::INFINITY;
+        for j in j_start..=j_end {
+            let reachable = match (i, j) {
+                (0, 0) => 0.0,
+                (0, _) => curr[j - 1],
+                (_, 0) => prev[j],
+                _ => prev[j].min(curr[j - 1]).min(prev[j - 1]),
+            };
+            curr[j] = reachable.max(euclidean_dist(point, &t[j]));
+            row_min = row_min.min(curr[j]);
+        }
+        // the largest gap never shrinks along a matching
+        if row_min > cutoff {
+            return f64::INFINITY;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[m - 1]
+}
+
+/// The two channels of SHARK2: both paths are resampled to `points` equidistant points, then
+/// compared point by point once normalized for shape and once as drawn for location.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Shark2 {
+    pub points: usize,
+    /// Weight of the mean distance between the normalized paths, in units of their bounding box.
+    pub shape_weight: f64,
+    /// Weight of the mean distance between the paths on the keyboard, in key units.
+    pub location_weight: f64,
+}
+
+impl Default for Shark2 {
+    fn default() -> Self {
+        Self { points: 32, shape_weight: 1.0, location_weight: 1.0 }
+    }
+}
+
+impl PathDistance for Shark2 {
+    fn distance(&self, input: &[Point], template: &[Point], _window: usize, _cutoff: f64) -> f64 {
+        if input.is_empty() || template.is_empty() {
+            return f64::INFINITY;
+        }
+        let (s, t) = (resample_uniform(input, self.points), resample_uniform(template, self.points));
+        let location = mean_distance(&s, &t);
+        let shape = mean_distance(&normalize_path(&s), &normalize_path(&t));
+        (self.shape_weight * shape + self.location_weight * location) * input.len() as f64
+    }
+}
+
+fn mean_distance(s: &[Point], t: &[Point]) -> f64 {
+    s.iter().zip(t).map(|(a, b)| euclidean_dist(a, b)).sum::<f64>() / s.len() as f64
+}
+
+/// One metric of a [`DistanceMetric::Blend`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlendComponent {
+    pub metric: DistanceMetric,
+    pub weight: f64,
+}
+
+/// The path distance an engine ranks words with.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "metric", rename_all = "snake_case")]
+pub enum DistanceMetric {
+    /// [`Dtw`]. The only metric that uses the configured [`crate::KeyModel`] and that
+    /// [`crate::SwipeSession`] can update incrementally.
+    #[default]
+    Dtw,
+    DerivativeDtw,
+    Frechet,
+    Shark2(Shark2),
+    /// Weighted sum of other metrics.
+    Blend { components: Vec<BlendComponent> },
+}
+
+impl PathDistance for DistanceMetric {
+    fn distance(&self, input: &[Point], template: &[Point], window: usize, cutoff: f64) -> f64 {
+        match self {
+            Self::Dtw => Dtw.distance(input, template, window, cutoff),
+            Self::DerivativeDtw => DerivativeDtw.distance(input, template, window, cutoff),
+            Self::Frechet => DiscreteFrechet.distance(input, template, window, cutoff),
+            Self::Shark2(shark2) => shark2.distance(input, template, window, cutoff),
+            Self::Blend { components } => {
+                let mut total = 0.0;
+                for component in components.iter().filter(|c| c.weight > 0.0) {
+                    // every component is non-negative, so none may exceed the cutoff on its own
+                    total += component.weight * component.metric.distance(input, template, window, cutoff / component.weight);
+                    if total > cutoff {
+                        return f64::INFINITY;
+                    }
+                }
+                total
+            }
+        }
+    }
+}
+
+impl DistanceMetric {
+    /// Checks that blend weights are non-negative and SHARK2 resamples to at least 2 points.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Shark2(shark2) => {
+                if shark2.points < 2 {
+                    return Err(format!("shark2 needs at least 2 points, got {}", shark2.points));
+                }
+                if ![shark2.shape_weight, shark2.location_weight].iter().all(|w| w.is_finite() && *w >= 0.0) {
+                    return Err("shark2 weights must be finite, non-negative numbers".to_string());
+                }
+                Ok(())
+            }
+            Self::Blend { components } => {
+                if components.is_empty() {
+                    return Err("blend has no metrics".to_string());
+                }
+                for component in components {
+                    if !component.weight.is_finite() || component.weight < 0.0 {
+                        return Err(format!("blend weight must be a finite, non-negative number, got {}", component.weight));
+                    }
+                    component.metric.validate()?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
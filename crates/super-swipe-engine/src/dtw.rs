@@ -2,8 +2,13 @@ use crate::keyboard::euclidean_dist;
 use swipe_types::types::Point;
 
 pub fn dtw_distance_fast(s: &[Point], t: &[Point], window: usize, cutoff: f64) -> f64 {
-    let n = s.len();
-    let m = t.len();
+    dtw_banded_with(s.len(), t.len(), window, cutoff, |i, j| euclidean_dist(&s[i], &t[j]))
+}
+
+/// Banded DTW between sequences of `n` and `m` points, where `cost(i, j)` is the cost of
+/// aligning point `i` of the first with point `j` of the second. Gives up and returns
+/// infinity once every cell of a row is above `cutoff`.
+pub fn dtw_banded_with<F: Fn(usize, usize) -> f64>(n: usize, m: usize, window: usize, cutoff: f64, cost: F) -> f64 {
     if n == 0 || m == 0 {
         return f64::INFINITY;
     }
@@ -28,9 +33,8 @@ pub fn dtw_distance_fast(s: &[Point], t: &[Point], window: usize, cutoff: f64) -
 
         let mut row_min = f64::INFINITY;
         for j in j_start..=j_end {
-            let cost = euclidean_dist(&s[i - 1], &t[j - 1]);
             let prev_min = prev[j].min(curr[j - 1]).min(prev[j - 1]);
-            curr[j] = cost + prev_min;
+            curr[j] = cost(i - 1, j - 1) + prev_min;
             row_min = row_min.min(curr[j]);
         }
 
@@ -0,0 +1,82 @@
+use codes_iso_639::part_1::LanguageCode;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use swipe_types::model::ModelFormatError;
+
+#[derive(Debug)]
+pub enum EngineLoadError {
+    /// The model could not be downloaded or stored in the download cache.
+    Network { url: String, source: Box<dyn Error + Send + Sync> },
+    /// The model file could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// The model file is not a valid bincode dictionary.
+    Decode(bincode::error::DecodeError),
+    /// The model file was written by a newer, incompatible format version.
+    UnsupportedModelVersion { found: u16, supported: u16 },
+    /// The model contents don't match the checksum or word count in its header.
+    CorruptModel(ModelFormatError),
+    /// The model file doesn't match the SHA-256 configured in its [`crate::ModelSource`].
+    ChecksumMismatch { path: PathBuf, expected: String, found: String },
+    /// The model was built for a different language than the one requested.
+    LanguageMismatch { expected: String, found: String },
+    /// No model exists for the language: its embedded model, model file or download is missing.
+    UnsupportedLanguage(LanguageCode),
+    /// The model has to be downloaded, but the `download` feature is disabled and the
+    /// `embedded-models` feature didn't provide it either.
+    NoModelSource(LanguageCode),
+    InvalidLayout(String),
+    /// An [`crate::EngineConfig`] setting is out of range.
+    InvalidConfig(String),
+}
+
+impl fmt::Display for EngineLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network { url, .. } => write!(f, "could not fetch model from {}", url),
+            Self::Io { path, .. } => write!(f, "could not read model file {}", path.display()),
+            Self::Decode(_) => write!(f, "could not decode model"),
+            Self::UnsupportedModelVersion { found, supported } => {
+                write!(f, "model format version {} is not supported, expected at most {}", found, supported)
+            }
+            Self::CorruptModel(_) => write!(f, "model file is corrupt"),
+            Self::ChecksumMismatch { path, expected, found } => {
+                write!(f, "model file {} has SHA-256 {}, expected {}", path.display(), found, expected)
+            }
+            Self::LanguageMismatch { expected, found } => {
+                write!(f, "model is for language {}, expected {}", found, expected)
+            }
+            Self::UnsupportedLanguage(lang_code) => write!(f, "no model is available for language {}", lang_code),
+            Self::NoModelSource(lang_code) => write!(
+                f,
+                "no model source for language {}: enable the `download` feature to fetch it, the `embedded-models` feature to compile it in, or load a model file directly",
+                lang_code
+            ),
+            Self::InvalidLayout(reason) => write!(f, "invalid keyboard layout: {}", reason),
+            Self::InvalidConfig(reason) => write!(f, "invalid engine configuration: {}", reason),
+        }
+    }
+}
+
+impl Error for EngineLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Network { source, .. } => Some(source.as_ref()),
+            Self::Io { source, .. } => Some(source),
+            Self::Decode(source) => Some(source),
+            Self::CorruptModel(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ModelFormatError> for EngineLoadError {
+    fn from(e: ModelFormatError) -> Self {
+        match e {
+            ModelFormatError::UnsupportedVersion { found, supported } => Self::UnsupportedModelVersion { found, supported },
+            ModelFormatError::Decode(e) => Self::Decode(e),
+            e => Self::CorruptModel(e),
+        }
+    }
+}
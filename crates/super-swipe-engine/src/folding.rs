@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use swipe_types::types::Point;
+
+/// Letters with diacritics and the keys they are typed with on a layout without them.
+const LATIN_FOLDS: &[(&str, &str)] = &[
+    ("àáâãäåāăą", "a"),
+    ("çćĉċč", "c"),
+    ("ďđ", "d"),
+    ("èéêëēĕėęě", "e"),
+    ("ĝğġģ", "g"),
+    ("ĥħ", "h"),
+    ("ìíîïĩīĭįı", "i"),
+    ("ĵ", "j"),
+    ("ķ", "k"),
+    ("ĺļľŀł", "l"),
+    ("ñńņňŉ", "n"),
+    ("òóôõöøōŏő", "o"),
+    ("ŕŗř", "r"),
+    ("śŝşšș", "s"),
+    ("ţťŧț", "t"),
+    ("ùúûüũūŭůűų", "u"),
+    ("ŵ", "w"),
+    ("ýÿŷ", "y"),
+    ("źżž", "z"),
+    ("æ", "ae"),
+    ("œ", "oe"),
+    ("ß", "ss"),
+];
+
+/// Extra folds of letters that only some languages spell out differently.
+const LANGUAGE_FOLDS: &[(&str, &[(char, &str)])] = &[
+    ("da", &[('ø', "oe"), ('å', "aa")]),
+    ("nb", &[('ø', "oe"), ('å', "aa")]),
+    ("no", &[('ø', "oe"), ('å', "aa")]),
+    ("is", &[('þ', "th"), ('ð', "d")]),
+];
+
+/// Maps letters that aren't on the layout, such as accented ones, to the keys they are typed with.
+///
+/// Folding only changes the path of a word: predictions keep the accented dictionary form,
+/// and when several forms share a path their frequencies and bigrams decide between them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FoldingTable {
+    folds: HashMap<char, String>,
+}
+
+impl FoldingTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Folds common Latin diacritics and ligatures, plus the letters `lang` spells out
+    /// differently. `lang` is an ISO 639-1 code; unknown or empty codes get only the Latin folds.
+    pub fn for_language(lang: &str) -> Self {
+        let mut table = Self::new();
+        for (letters, base) in LATIN_FOLDS {
+            for letter in letters.chars() {
+                table.insert(letter, base);
+            }
+        }
+        if let Some((_, folds)) = LANGUAGE_FOLDS.iter().find(|(code, _)| *code == lang) {
+            for (letter, base) in folds.iter() {
+                table.insert(*letter, base);
+            }
+        }
+        table
+    }
+
+    /// Folds `letter`, which should be lowercase, to `base`.
+    pub fn insert(&mut self, letter: char, base: &str) {
+        self.folds.insert(letter, base.to_string());
+    }
+
+    pub fn get(&self, letter: char) -> Option<&str> {
+        self.folds.get(&letter).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.folds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folds.is_empty()
+    }
+
+    /// Lowercases `word` and folds the letters that have no key on `layout`.
+    /// Letters with their own key, like 'ü' on a German layout, are kept.
+    pub fn fold(&self, word: &str, layout: &HashMap<char, Point>) -> String {
+        let mut folded = String::with_capacity(word.len());
+        for c in word.chars().flat_map(char::to_lowercase) {
+            match self.folds.get(&c) {
+                Some(base) if !layout.contains_key(&c) => folded.push_str(base),
+                _ => folded.push(c),
+            }
+        }
+        folded
+    }
+}
+
+/// Which [`FoldingTable`] an engine uses.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Folding {
+    /// The table for the language in the model header, or the one the model was loaded for if
+    /// the header has none.
+    #[default]
+    Auto,
+    /// Letters are matched as they are spelled.
+    Disabled,
+    Custom { table: FoldingTable },
+}
+
+impl Folding {
+    /// Table to use for a model of `language`.
+    pub fn table(&self, language: &str) -> FoldingTable {
+        match self {
+            Self::Auto => FoldingTable::for_language(language),
+            Self::Disabled => FoldingTable::new(),
+            Self::Custom { table } => table.clone(),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::keyboard::{euclidean_dist, nearest_key};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+use swipe_types::types::{Point, SwipeTrace};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyHitKind {
+    Start,
+    /// The finger slowed down over the key.
+    Dwell,
+    /// The trace turned sharply over the key.
+    Corner,
+    End,
+}
+
+/// A key the user likely meant to hit, found in a raw trace.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyHit {
+    pub key: char,
+    pub kind: KeyHitKind,
+    /// Index of the sample the hit was detected at.
+    pub sample_index: usize,
+    pub point: Point,
+    pub t_ms: f64,
+    /// How clearly the trace shows the hit, between 0 and 1. For the start and end of the
+    /// trace, how clearly the sample lies on one key rather than between keys.
+    pub confidence: f64,
+}
+
+impl fmt::Display for KeyHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            KeyHitKind::Start => "start",
+            KeyHitKind::Dwell => "dwell",
+            KeyHitKind::Corner => "corner",
+            KeyHitKind::End => "end",
+        };
+        write!(f, "{} {} {:.2} @{:.0}ms", self.key, kind, self.confidence, self.t_ms)
+    }
+}
+
+/// Thresholds for [`detect_key_hits`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyHitParams {
+    /// Speed minima slower than this fraction of the median speed count as dwells.
+    pub dwell_speed_ratio: f64,
+    /// Smallest change of direction that counts as a corner, in radians.
+    pub corner_angle: f64,
+    /// Distance before and after a sample over which its direction change is measured, in key units.
+    pub corner_span: f64,
+}
+
+impl Default for KeyHitParams {
+    fn default() -> Self {
+        Self {
+            dwell_speed_ratio: 0.4,
+            corner_angle: PI / 3.0,
+            corner_span: 0.5,
+        }
+    }
+}
+
+/// Finds the keys a trace most likely targets from its speed minima and sharp turns,
+/// always including the keys under the first and last sample, see [`key_certainty`].
+/// Consecutive hits on the same key are merged, keeping the most confident one.
+pub fn detect_key_hits(trace: &SwipeTrace, layout: &HashMap<char, Point>, params: &KeyHitParams) -> Vec<KeyHit> {
+    let samples = &trace.samples;
+    let points = trace.points();
+    if points.is_empty() {
+        return vec![];
+    }
+    let n = points.len();
+
+    let mut found = vec![(0, KeyHitKind::Start, key_certainty(&points[0], layout))];
+
+    let speeds: Vec<f64> = (0..n)
+        .map(|i| {
+            let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+            let dt = samples[b].t_ms - samples[a].t_ms;
+            if dt > 0.0 {
+                euclidean_dist(&points[a], &points[b]) / dt
+            } else {
+                f64::INFINITY
+            }
+        })
+        .collect();
+    let mut finite_speeds: Vec<f64> = speeds.iter().cloned().filter(|s| s.is_finite()).collect();
+    if !finite_speeds.is_empty() {
+        finite_speeds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let dwell_speed = finite_speeds[finite_speeds.len() / 2] * params.dwell_speed_ratio;
+        for i in 1..n.saturating_sub(1) {
+            if speeds[i] < dwell_speed && speeds[i] <= speeds[i - 1] && speeds[i] <= speeds[i + 1] {
+                found.push((i, KeyHitKind::Dwell, 1.0 - speeds[i] / dwell_speed));
+            }
+        }
+    }
+
+    let angles: Vec<f64> = (0..n).map(|i| turn_angle(&points, i, params.corner_span)).collect();
+    for i in 1..n.saturating_sub(1) {
+        if angles[i] >= params.corner_angle && angles[i] >= angles[i - 1] && angles[i] > angles[i + 1] {
+            let confidence = ((angles[i] - params.corner_angle) / (PI - params.corner_angle)).clamp(0.0, 1.0);
+            found.push((i, KeyHitKind::Corner, confidence.max(0.5)));
+        }
+    }
+
+    if n > 1 {
+        found.push((n - 1, KeyHitKind::End, key_certainty(&points[n - 1], layout)));
+    }
+    found.sort_by_key(|(index, _, _)| *index);
+
+    let mut hits: Vec<KeyHit> = Vec::new();
+    for (index, kind, confidence) in found {
+        let Some(key) = nearest_key(&points[index], layout) else {
+            continue;
+        };
+        let hit = KeyHit { key, kind, sample_index: index, point: points[index], t_ms: samples[index].t_ms, confidence };
+        match hits.last_mut() {
+            Some(last) if last.key == key => {
+                // the start and end of the trace take precedence over hits in between
+                let keep_last = last.kind == KeyHitKind::Start || (kind != KeyHitKind::End && last.confidence >= confidence);
+                if !keep_last {
+                    *last = hit;
+                }
+            }
+            _ => hits.push(hit),
+        }
+    }
+    hits
+}
+
+/// How clearly `point` lies on a single key: 1 on a key centre, falling to 0 halfway between
+/// the closest key and the next one.
+pub fn key_certainty(point: &Point, layout: &HashMap<char, Point>) -> f64 {
+    let Some(closest) = nearest_key(point, layout).map(|key| layout[&key]) else {
+        return 0.0;
+    };
+    let next = layout.values().filter(|key| **key != closest).map(|key| euclidean_dist(point, key)).fold(f64::INFINITY, f64::min);
+    if next == 0.0 {
+        return 0.0;
+    }
+    1.0 - euclidean_dist(point, &closest) / next
+}
+
+/// The keys of `hits` in order, for logging or for [`crate::SwipeEngine::predict`].
+pub fn key_string(hits: &[KeyHit]) -> String {
+    hits.iter().map(|hit| hit.key).collect()
+}
+
+/// Angle between the direction into and out of `points[i]`, measured to the nearest
+/// points at least `span` away on either side.
+fn turn_angle(points: &[Point], i: usize, span: f64) -> f64 {
+    let before = (0..i).rev().find(|&j| euclidean_dist(&points[j], &points[i]) >= span);
+    let after = (i + 1..points.len()).find(|&j| euclidean_dist(&points[j], &points[i]) >= span);
+    let (Some(before), Some(after)) = (before, after) else {
+        return 0.0;
+    };
+
+    let (in_x, in_y) = (points[i].x - points[before].x, points[i].y - points[before].y);
+    let (out_x, out_y) = (points[after].x - points[i].x, points[after].y - points[i].y);
+    let cos = (in_x * out_x + in_y * out_y) / ((in_x.hypot(in_y)) * (out_x.hypot(out_y)));
+    cos.clamp(-1.0, 1.0).acos()
+}
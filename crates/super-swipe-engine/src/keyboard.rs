@@ -1,5 +1,11 @@
+use crate::EngineLoadError;
+use codes_iso_639::part_1::LanguageCode;
+use serde::{Deserialize, Serialize};
 use swipe_types::types::Point;
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
 
 pub fn get_keyboard_layout() -> HashMap<char, Point> {
     let mut layout = HashMap::new();
@@ -25,16 +31,30 @@ pub fn get_keyboard_layout() -> HashMap<char, Point> {
 }
 
 pub fn get_word_path(word: &str, layout: &HashMap<char, Point>) -> Vec<Point> {
+    get_word_path_with_step(word, layout, 0.5)
+}
+
+/// Like [`get_word_path`], interpolating a point every `step_size` key units between keys.
+pub fn get_word_path_with_step(word: &str, layout: &HashMap<char, Point>, step_size: f64) -> Vec<Point> {
+    get_word_path_with_loops(word, layout, step_size, 0.0)
+}
+
+/// Like [`get_word_path_with_step`], drawing a loop of `loop_radius` key units on keys that
+/// are repeated, so "too" and "to" get different paths. A radius of 0 collapses repeated keys.
+pub fn get_word_path_with_loops(word: &str, layout: &HashMap<char, Point>, step_size: f64, loop_radius: f64) -> Vec<Point> {
     let key_points: Vec<Point> = word
         .chars()
         .filter_map(|c| layout.get(&c.to_ascii_lowercase()).cloned())
         .collect();
+    path_through_keys(&key_points, step_size, loop_radius)
+}
 
+/// Path through the key centres `key_points`, interpolated like [`get_word_path_with_loops`].
+pub fn path_through_keys(key_points: &[Point], step_size: f64, loop_radius: f64) -> Vec<Point> {
     if key_points.is_empty() {
         return vec![];
     }
 
-    let step_size = 0.5;
     let mut full_path = vec![key_points[0]];
 
     for i in 1..key_points.len() {
@@ -42,7 +62,9 @@ pub fn get_word_path(word: &str, layout: &HashMap<char, Point>) -> Vec<Point> {
         let p2 = key_points[i];
 
         let dist = euclidean_dist(&p1, &p2);
-        if dist > step_size {
+        if dist == 0.0 {
+            full_path.extend(double_letter_loop(&p1, loop_radius, step_size));
+        } else if dist > step_size {
             let num_steps = (dist / step_size) as i32;
             let dx = (p2.x - p1.x) / num_steps as f64;
             let dy = (p2.y - p1.y) / num_steps as f64;
@@ -59,20 +81,525 @@ pub fn get_word_path(word: &str, layout: &HashMap<char, Point>) -> Vec<Point> {
     full_path
 }
 
+/// Points of a loop of `radius` that leaves `key` upwards and comes back to it,
+/// excluding `key` itself. Empty for a radius of 0.
+pub fn double_letter_loop(key: &Point, radius: f64, step_size: f64) -> Vec<Point> {
+    if radius <= 0.0 {
+        return vec![];
+    }
+    let centre = Point { x: key.x, y: key.y - radius };
+    let num_steps = ((2.0 * std::f64::consts::PI * radius / step_size).ceil() as i32).max(4);
+    (1..num_steps)
+        .map(|s| {
+            let angle = 2.0 * std::f64::consts::PI * s as f64 / num_steps as f64;
+            Point { x: centre.x + radius * angle.sin(), y: centre.y + radius * angle.cos() }
+        })
+        .collect()
+}
+
+/// Keys that appear twice in a row in `key_points`, once per repetition.
+pub fn repeated_keys(key_points: &[Point]) -> Vec<Point> {
+    key_points.windows(2).filter(|w| w[0] == w[1]).map(|w| w[0]).collect()
+}
+
+/// Returns the key whose centre is closest to `point`.
+pub fn nearest_key(point: &Point, layout: &HashMap<char, Point>) -> Option<char> {
+    layout
+        .iter()
+        .min_by(|(_, a), (_, b)| {
+            euclidean_dist(point, a)
+                .partial_cmp(&euclidean_dist(point, b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|(key, _)| *key)
+}
+
+/// Resamples a raw trace to points spaced `step_size` apart along its arc length,
+/// keeping the first and last point.
+pub fn resample_path(path: &[Point], step_size: f64) -> Vec<Point> {
+    if path.is_empty() {
+        return vec![];
+    }
+
+    let mut resampled = vec![path[0]];
+    let mut prev = path[0];
+    let mut carried = 0.0;
+    for &p in &path[1..] {
+        let mut seg_start = prev;
+        let mut seg_len = euclidean_dist(&seg_start, &p);
+        while carried + seg_len >= step_size && seg_len > 0.0 {
+            let t = (step_size - carried) / seg_len;
+            let new_point = Point {
+                x: seg_start.x + (p.x - seg_start.x) * t,
+                y: seg_start.y + (p.y - seg_start.y) * t,
+            };
+            resampled.push(new_point);
+            seg_start = new_point;
+            seg_len = euclidean_dist(&seg_start, &p);
+            carried = 0.0;
+        }
+        carried += seg_len;
+        prev = p;
+    }
+
+    let last = path[path.len() - 1];
+    if euclidean_dist(resampled.last().unwrap(), &last) > 0.0 {
+        resampled.push(last);
+    }
+    resampled
+}
+
 pub fn euclidean_dist(p1: &Point, p2: &Point) -> f64 {
     ((p1.x - p2.x).powi(2) + (p1.y - p2.y).powi(2)).sqrt()
 }
 
 pub fn simplify_path(path: &[Point]) -> Vec<Point> {
+    simplify_path_with_threshold(path, 0.01)
+}
+
+/// Like [`simplify_path`], dropping points closer than `threshold` to the previous kept point.
+pub fn simplify_path_with_threshold(path: &[Point], threshold: f64) -> Vec<Point> {
     if path.is_empty() {
         return vec![];
     }
 
     let mut new_path = vec![path[0]];
     for p in path.iter().skip(1) {
-        if euclidean_dist(p, new_path.last().unwrap()) > 0.01 {
+        if euclidean_dist(p, new_path.last().unwrap()) > threshold {
             new_path.push(*p);
         }
     }
     new_path
 }
+
+/// A keyboard layout as stored in a TOML or JSON layout file.
+///
+/// Positions and sizes are in key units. The origin is the centre of a standard key at the
+/// left of the top row, so the built-in QWERTY layout has 'q' at (0, 0) and 'a' at (0.5, 1).
+///
+/// ```toml
+/// name = "qwerty"
+///
+/// [[rows]]
+/// keys = [{ label = "q" }, { label = "w" }, { label = "e" }]
+///
+/// [[rows]]
+/// offset = 0.5
+/// keys = [{ label = "a" }, { label = "s" }, { label = "Enter", outputs = ["\n"], width = 2.0 }]
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyboardLayout {
+    pub name: String,
+    /// Width of keys that don't set their own.
+    #[serde(default = "default_key_size")]
+    pub key_width: f64,
+    /// Height of rows that don't set their own.
+    #[serde(default = "default_key_size")]
+    pub key_height: f64,
+    pub rows: Vec<LayoutRow>,
+    /// Further layers, such as numbers and symbols, drawn over the same area as `rows`.
+    #[serde(default)]
+    pub layers: Vec<LayoutLayer>,
+}
+
+/// Name that switch keys use for the layer described by [`KeyboardLayout::rows`].
+pub const BASE_LAYER: &str = "base";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutLayer {
+    pub name: String,
+    pub rows: Vec<LayoutRow>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutRow {
+    /// Top of the row. Defaults to just below the previous row.
+    pub y: Option<f64>,
+    /// Stagger: how far the first key is moved right.
+    pub offset: f64,
+    pub height: Option<f64>,
+    pub keys: Vec<LayoutKey>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutKey {
+    pub label: String,
+    /// Characters typed with the key. Defaults to the lowercase label if it is a single character.
+    pub outputs: Vec<char>,
+    pub width: Option<f64>,
+    /// Left edge of the key, for gaps in a row. Defaults to the right edge of the previous key.
+    pub x: Option<f64>,
+    /// Name of the layer the key switches to, [`BASE_LAYER`] for the main one.
+    pub switch_to: Option<String>,
+}
+
+/// A key of a [`KeyboardLayout`] with its final position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedKey {
+    pub label: String,
+    pub outputs: Vec<char>,
+    pub rect: KeyRect,
+    pub switch_to: Option<String>,
+}
+
+/// The keys of one layer of a layout, as the engine uses them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub keys: HashMap<char, KeyRect>,
+    /// Keys that switch to another layer, with the index of that layer.
+    pub switches: Vec<(usize, KeyRect)>,
+}
+
+impl Layer {
+    /// A single layer without switch keys.
+    pub fn base(keys: HashMap<char, KeyRect>) -> Self {
+        Self { name: BASE_LAYER.to_string(), keys, switches: vec![] }
+    }
+
+    pub fn centres(&self) -> HashMap<char, Point> {
+        self.keys.iter().map(|(c, rect)| (*c, rect.centre)).collect()
+    }
+
+    /// Switch key on this layer that leads to layer `to`.
+    pub fn switch_to(&self, to: usize) -> Option<&KeyRect> {
+        self.switches.iter().find(|(target, _)| *target == to).map(|(_, rect)| rect)
+    }
+}
+
+/// A key rectangle, given by its centre and size.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyRect {
+    pub centre: Point,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl KeyRect {
+    fn overlaps(&self, other: &KeyRect) -> bool {
+        // touching edges don't count, so allow for rounding in summed widths
+        const EPSILON: f64 = 1e-9;
+        (self.centre.x - other.centre.x).abs() < (self.width + other.width) / 2.0 - EPSILON
+            && (self.centre.y - other.centre.y).abs() < (self.height + other.height) / 2.0 - EPSILON
+    }
+}
+
+/// Gives every key of a layout without sizes a unit rectangle.
+pub fn unit_key_rects(layout: &HashMap<char, Point>) -> HashMap<char, KeyRect> {
+    layout.iter().map(|(c, centre)| (*c, KeyRect { centre: *centre, width: 1.0, height: 1.0 })).collect()
+}
+
+/// How the cost of a touch at a point is derived from a key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum KeyModel {
+    /// Euclidean distance to the key centre, ignoring the key size.
+    #[default]
+    Centre,
+    /// Negative log-likelihood of the touch under a 2D Gaussian centred on the key, with
+    /// standard deviations `sigma` times the key width and height. Touches on wide keys
+    /// can stray further sideways than on narrow ones.
+    Gaussian { sigma: f64 },
+}
+
+impl KeyModel {
+    /// Cost of a touch at `point` meant for a `width` by `height` key centred on `centre`.
+    pub fn cost(&self, point: &Point, centre: &Point, width: f64, height: f64) -> f64 {
+        match self {
+            Self::Centre => euclidean_dist(point, centre),
+            Self::Gaussian { sigma } => {
+                let dx = (point.x - centre.x) / (sigma * width);
+                let dy = (point.y - centre.y) / (sigma * height);
+                0.5 * (dx * dx + dy * dy)
+            }
+        }
+    }
+
+    /// Likelihood of a touch at `point` for `key`, up to a factor shared by all keys.
+    pub fn likelihood(&self, point: &Point, key: &KeyRect) -> f64 {
+        match self {
+            Self::Centre => (-self.cost(point, &key.centre, key.width, key.height)).exp(),
+            Self::Gaussian { sigma } => {
+                (-self.cost(point, &key.centre, key.width, key.height)).exp() / (sigma * sigma * key.width * key.height)
+            }
+        }
+    }
+}
+
+/// The key a tap at `point` most likely meant, for tap correction.
+pub fn most_likely_key(point: &Point, keys: &HashMap<char, KeyRect>, model: &KeyModel) -> Option<char> {
+    keys.iter()
+        .max_by(|(_, a), (_, b)| {
+            model.likelihood(point, a)
+                .partial_cmp(&model.likelihood(point, b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|(key, _)| *key)
+}
+
+fn default_key_size() -> f64 {
+    1.0
+}
+
+impl LayoutKey {
+    fn outputs(&self) -> Vec<char> {
+        if !self.outputs.is_empty() {
+            return self.outputs.clone();
+        }
+        let mut label = self.label.chars().flat_map(char::to_lowercase);
+        match (label.next(), label.next()) {
+            (Some(c), None) => vec![c],
+            _ => vec![],
+        }
+    }
+}
+
+impl KeyboardLayout {
+    pub fn from_toml_str(s: &str) -> Result<Self, EngineLoadError> {
+        toml::from_str(s).map_err(|e| EngineLoadError::InvalidLayout(e.to_string()))
+    }
+
+    pub fn from_json_str(s: &str) -> Result<Self, EngineLoadError> {
+        serde_json::from_str(s).map_err(|e| EngineLoadError::InvalidLayout(e.to_string()))
+    }
+
+    /// Reads a layout file, as JSON if its extension is `.json` and as TOML otherwise.
+    pub fn from_file(path: &Path) -> Result<Self, EngineLoadError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| EngineLoadError::InvalidLayout(format!("could not read {}: {}", path.display(), e)))?;
+        let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_str(&contents),
+            _ => Self::from_toml_str(&contents),
+        };
+        parsed.map_err(|e| match e {
+            EngineLoadError::InvalidLayout(reason) => EngineLoadError::InvalidLayout(format!("{}: {}", path.display(), reason)),
+            e => e,
+        })
+    }
+
+    /// Positions every key of the main layer, laying out each row from left to right.
+    pub fn placed_keys(&self) -> Vec<PlacedKey> {
+        self.place_rows(&self.rows)
+    }
+
+    fn place_rows(&self, rows: &[LayoutRow]) -> Vec<PlacedKey> {
+        let mut placed = Vec::new();
+        let mut row_top = 0.0;
+        for row in rows {
+            let top = row.y.unwrap_or(row_top);
+            let height = row.height.unwrap_or(self.key_height);
+            let mut left = row.offset;
+            for key in &row.keys {
+                let width = key.width.unwrap_or(self.key_width);
+                if let Some(x) = key.x {
+                    left = x;
+                }
+                let centre = Point {
+                    x: left + (width - self.key_width) / 2.0,
+                    y: top + (height - self.key_height) / 2.0,
+                };
+                placed.push(PlacedKey {
+                    label: key.label.clone(),
+                    outputs: key.outputs(),
+                    rect: KeyRect { centre, width, height },
+                    switch_to: key.switch_to.clone(),
+                });
+                left += width;
+            }
+            row_top = top + height;
+        }
+        placed
+    }
+
+    /// Checks sizes and positions, that keys don't overlap, that every character is
+    /// typed with exactly one key of a layer, and that switch keys lead to existing layers.
+    pub fn validate(&self) -> Result<(), EngineLoadError> {
+        let invalid = |reason: String| Err(EngineLoadError::InvalidLayout(format!("{}: {}", self.name, reason)));
+        if !(self.key_width.is_finite() && self.key_width > 0.0 && self.key_height.is_finite() && self.key_height > 0.0) {
+            return invalid(format!("key size {}x{} must be positive", self.key_width, self.key_height));
+        }
+
+        let names: Vec<&str> = std::iter::once(BASE_LAYER).chain(self.layers.iter().map(|layer| layer.name.as_str())).collect();
+        if let Some((i, name)) = names.iter().enumerate().find(|(i, name)| names[..*i].contains(name)) {
+            return invalid(format!("layer {} is named {:?}, which is already taken", i, name));
+        }
+        self.validate_rows(BASE_LAYER, &self.rows, &names)?;
+        for layer in &self.layers {
+            self.validate_rows(&layer.name, &layer.rows, &names)?;
+        }
+        Ok(())
+    }
+
+    fn validate_rows(&self, layer: &str, rows: &[LayoutRow], layer_names: &[&str]) -> Result<(), EngineLoadError> {
+        let invalid = |reason: String| Err(EngineLoadError::InvalidLayout(format!("{} ({} layer): {}", self.name, layer, reason)));
+        let keys = self.place_rows(rows);
+        let mut seen: HashMap<char, &str> = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            let KeyRect { centre, width, height } = key.rect;
+            if !(width.is_finite() && width > 0.0 && height.is_finite() && height > 0.0) {
+                return invalid(format!("key {:?} has size {}x{}", key.label, width, height));
+            }
+            if !centre.x.is_finite() || !centre.y.is_finite() {
+                return invalid(format!("key {:?} has non-finite position ({}, {})", key.label, centre.x, centre.y));
+            }
+            if let Some(other) = keys[..i].iter().find(|other| other.rect.overlaps(&key.rect)) {
+                return invalid(format!("keys {:?} and {:?} overlap", other.label, key.label));
+            }
+            for &c in &key.outputs {
+                if let Some(other) = seen.insert(c, &key.label) {
+                    return invalid(format!("{:?} is typed with both {:?} and {:?}", c, other, key.label));
+                }
+            }
+            if let Some(target) = key.switch_to.as_deref().filter(|target| !layer_names.contains(target)) {
+                return invalid(format!("key {:?} switches to unknown layer {:?}", key.label, target));
+            }
+        }
+        if seen.is_empty() {
+            return invalid("layer has no keys".to_string());
+        }
+        Ok(())
+    }
+
+    /// Validates the layout and returns its layers, the main one first.
+    pub fn layer_keys(&self) -> Result<Vec<Layer>, EngineLoadError> {
+        self.validate()?;
+        let names: Vec<&str> = std::iter::once(BASE_LAYER).chain(self.layers.iter().map(|layer| layer.name.as_str())).collect();
+        let all_rows = std::iter::once(&self.rows).chain(self.layers.iter().map(|layer| &layer.rows));
+        Ok(names
+            .iter()
+            .zip(all_rows)
+            .map(|(name, rows)| {
+                let mut layer = Layer { name: name.to_string(), keys: HashMap::new(), switches: vec![] };
+                for key in self.place_rows(rows) {
+                    if let Some(target) = &key.switch_to {
+                        let target = names.iter().position(|name| name == target).unwrap();
+                        layer.switches.push((target, key.rect));
+                    }
+                    for c in key.outputs {
+                        layer.keys.insert(c, key.rect);
+                    }
+                }
+                layer
+            })
+            .collect())
+    }
+
+    /// Validates the layout and maps each output character of the main layer to its key rectangle.
+    pub fn key_rects(&self) -> Result<HashMap<char, KeyRect>, EngineLoadError> {
+        Ok(self.layer_keys()?.swap_remove(0).keys)
+    }
+
+    /// Validates the layout and maps each output character of the main layer to the centre of its key,
+    /// the form [`crate::SwipeEngine`] takes.
+    pub fn to_layout(&self) -> Result<HashMap<char, Point>, EngineLoadError> {
+        Ok(self.key_rects()?.into_iter().map(|(c, rect)| (c, rect.centre)).collect())
+    }
+}
+
+/// Loads and validates a layout file, see [`KeyboardLayout::from_file`].
+pub fn load_layout(path: &Path) -> Result<HashMap<char, Point>, EngineLoadError> {
+    KeyboardLayout::from_file(path)?.to_layout()
+}
+
+/// Layouts that ship with the engine, defined in the crate's `layouts/` directory.
+///
+/// Only the letter rows are laid out, with the national letters that have their own key there.
+/// Letters typed from the number row or as alternates of a key, like 'é' on AZERTY and 'ß' on
+/// QWERTZ, are folded to their base key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinLayout {
+    Qwerty,
+    /// French, with ù next to m.
+    Azerty,
+    /// German, with ü ö ä next to the letters.
+    Qwertz,
+    Dvorak,
+    Colemak,
+    /// QWERTY with ñ.
+    Spanish,
+    /// Swedish and Finnish QWERTY with å ö ä.
+    Nordic,
+    /// Danish QWERTY with å æ ø.
+    Danish,
+    /// Norwegian QWERTY with å ø æ.
+    Norwegian,
+}
+
+impl BuiltinLayout {
+    pub const ALL: [BuiltinLayout; 9] = [
+        Self::Qwerty,
+        Self::Azerty,
+        Self::Qwertz,
+        Self::Dvorak,
+        Self::Colemak,
+        Self::Spanish,
+        Self::Nordic,
+        Self::Danish,
+        Self::Norwegian,
+    ];
+
+    /// The layout usually used to type `lang_code`, QWERTY if there is no better match.
+    pub fn for_language(lang_code: LanguageCode) -> Self {
+        match lang_code {
+            LanguageCode::Fr | LanguageCode::Br | LanguageCode::Oc => Self::Azerty,
+            LanguageCode::De | LanguageCode::Lb | LanguageCode::Cs | LanguageCode::Sk | LanguageCode::Hu
+            | LanguageCode::Hr | LanguageCode::Sl | LanguageCode::Bs | LanguageCode::Sq => Self::Qwertz,
+            LanguageCode::Es | LanguageCode::Gl | LanguageCode::Eu => Self::Spanish,
+            LanguageCode::Sv | LanguageCode::Fi => Self::Nordic,
+            LanguageCode::Da | LanguageCode::Fo => Self::Danish,
+            LanguageCode::Nb | LanguageCode::Nn | LanguageCode::No => Self::Norwegian,
+            _ => Self::Qwerty,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Qwerty => "qwerty",
+            Self::Azerty => "azerty",
+            Self::Qwertz => "qwertz",
+            Self::Dvorak => "dvorak",
+            Self::Colemak => "colemak",
+            Self::Spanish => "spanish",
+            Self::Nordic => "nordic",
+            Self::Danish => "danish",
+            Self::Norwegian => "norwegian",
+        }
+    }
+
+    fn source(self) -> &'static str {
+        match self {
+            Self::Qwerty => include_str!("../layouts/qwerty.toml"),
+            Self::Azerty => include_str!("../layouts/azerty.toml"),
+            Self::Qwertz => include_str!("../layouts/qwertz.toml"),
+            Self::Dvorak => include_str!("../layouts/dvorak.toml"),
+            Self::Colemak => include_str!("../layouts/colemak.toml"),
+            Self::Spanish => include_str!("../layouts/spanish.toml"),
+            Self::Nordic => include_str!("../layouts/nordic.toml"),
+            Self::Danish => include_str!("../layouts/danish.toml"),
+            Self::Norwegian => include_str!("../layouts/norwegian.toml"),
+        }
+    }
+
+    pub fn definition(self) -> KeyboardLayout {
+        KeyboardLayout::from_toml_str(self.source()).expect("built-in layouts are valid")
+    }
+
+    pub fn layout(self) -> HashMap<char, Point> {
+        self.definition().to_layout().expect("built-in layouts are valid")
+    }
+}
+
+impl FromStr for BuiltinLayout {
+    type Err = EngineLoadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|layout| layout.name() == s)
+            .ok_or_else(|| EngineLoadError::InvalidLayout(format!("no built-in layout named {:?}", s)))
+    }
+}
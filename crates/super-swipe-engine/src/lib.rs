@@ -1,17 +1,36 @@
 //! Swipe typing prediction engine
 
+pub mod builder;
+pub mod config;
+pub mod distance;
 pub mod dtw;
+pub mod error;
+pub mod folding;
+pub mod key_hits;
 pub mod keyboard;
+pub mod preprocess;
+pub mod retrieval;
+pub mod search;
+pub mod session;
+pub mod source;
+pub mod transform;
+mod trie;
+pub mod unknown_chars;
 
 
-use bincode;
 use codes_iso_639::part_1::LanguageCode;
-use dtw::dtw_distance_fast;
-use keyboard::{euclidean_dist, get_keyboard_layout, get_word_path, simplify_path};
+use dtw::{dtw_banded_with, dtw_distance_fast};
+use keyboard::{euclidean_dist, most_likely_key, nearest_key, unit_key_rects, path_through_keys, repeated_keys, resample_path, simplify_path_with_threshold};
+use std::collections::hash_map::{DefaultHasher, Entry};
 use std::collections::HashMap;
-use std::{env, fs};
-use std::path::{Path, PathBuf};
-use bincode::config::Configuration;
+use std::hash::{Hash, Hasher};
+use std::fs;
+use std::path::Path;
+use swipe_types::model::{decode_model, ModelHeader};
+use key_hits::{detect_key_hits, key_string};
+use retrieval::VpTree;
+use search::{PathSummary, QueryBounds};
+use trie::{PathTrie, TrieVisitor};
 use swipe_types::types::{Dictionary, Point, Prediction, WordInfo};
 
 pub use dtw::{dtw_distance, dtw_distance_fast as dtw_fast};
@@ -19,179 +38,816 @@ pub use keyboard::{
     euclidean_dist as euclidean_distance, get_keyboard_layout as keyboard_layout,
     get_word_path as word_path, simplify_path as path_simplify,
 };
+pub use keyboard::{load_layout, BuiltinLayout, KeyModel, KeyRect, Layer, LayoutLayer, BASE_LAYER, KeyboardLayout, LayoutKey, LayoutRow, PlacedKey};
 pub use swipe_types::types::Point as PointType;
-use cached_path::{cached_path};
+pub use swipe_types::types::{SwipeTrace, TouchSample};
+pub use builder::SwipeEngineBuilder;
+pub use config::EngineConfig;
+pub use distance::{BlendComponent, DerivativeDtw, DiscreteFrechet, DistanceMetric, Dtw, PathDistance, Shark2};
+pub use error::EngineLoadError;
+pub use folding::{Folding, FoldingTable};
+pub use key_hits::{KeyHit, KeyHitKind, KeyHitParams};
+pub use preprocess::PathPreprocessing;
+pub use retrieval::{embed_path, Retrieval};
+pub use search::{SearchStats, SearchStrategy};
+pub use session::SwipeSession;
+pub use source::{ModelLocation, ModelSource};
+pub use transform::{Bounds, LayoutTransform, ScreenMapping, ScreenRegion};
+pub use unknown_chars::UnknownCharPolicy;
+#[cfg(feature = "download")]
+use cached_path::cached_path;
+#[cfg(feature = "download")]
+use std::path::PathBuf;
+
+#[cfg(feature = "embedded-models")]
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/embedded_models.rs"));
+}
+
+/// A swipe prepared for matching against the word paths.
+struct SwipeQuery {
+    path: Vec<Point>,
+    /// Words starting on this key get no start penalty.
+    first_key: Option<Point>,
+    /// Candidates are the words starting on keys near this point. Words starting on
+    /// other keys than `first_key` are penalized by their distance to it.
+    first_pt: Point,
+    /// Words starting on keys within this distance of `first_pt` are candidates too.
+    start_radius: f64,
+    /// How sure the input is of its first key, between 0 and 1. Scales the start penalty.
+    start_confidence: f64,
+    /// Words ending on this key get no end penalty.
+    last_key: Option<Point>,
+    /// Words ending on other keys are penalized by their distance to this point.
+    last_pt: Point,
+    /// Like `start_confidence`, for the last key.
+    end_confidence: f64,
+    /// Keys detected in a raw trace, used as soft constraints.
+    hits: Vec<KeyHit>,
+    /// Keys repeated in a key input. `None` for raw traces.
+    repeated_keys: Option<Vec<Point>>,
+}
 
 /// Uses a Dynamic Time Warping (DTW) algorithm to compare swipe paths
 /// against a dictionary of words.
 pub struct SwipeEngine {
+    header: ModelHeader,
     dictionary: Dictionary,
     layout: HashMap<char, Point>,
-    pop_weight: f64,
-    bigram_weight: f64,
+    /// The main layer first. Its key centres are `layout`.
+    layers: Vec<Layer>,
+    config: EngineConfig,
+    folding: FoldingTable,
+    /// Ids of the paths starting on each key.
     by_first_letter: HashMap<char, Vec<usize>>,
-    word_paths: Vec<Vec<Point>>,
-}
-
-#[derive(Debug)]
-pub enum EngineLoadError {
-    ModelFileError(String),
-    UnsupportedLanguageError(String)
+    /// Centres of the keys each word is typed with, after the unknown character policy.
+    word_keys: Vec<Vec<Point>>,
+    /// Distinct word paths. Words with the same path, such as case variants or words that only
+    /// differ by skipped characters, share one so its distance is computed once.
+    paths: Vec<Vec<Point>>,
+    /// Words of each path, grouped by path: those of path `i` start at `path_word_starts[i]`.
+    path_words: Vec<usize>,
+    path_word_starts: Vec<usize>,
+    /// Summaries of `paths` for the lower bounds.
+    path_summaries: Vec<PathSummary>,
+    /// The paths of each `by_first_letter` bucket, with [`SearchStrategy::Trie`].
+    tries: HashMap<char, PathTrie>,
+    /// Embeddings of the paths of each `by_first_letter` bucket, with [`Retrieval::Ann`].
+    ann: HashMap<char, VpTree>,
+    /// Size of the key closest to each point of the paths, when the key model uses it.
+    path_key_sizes: Vec<Vec<(f64, f64)>>,
 }
 
 impl SwipeEngine {
+    /// Downloads the default model for `lang_code` into the local cache and returns its path.
+    #[cfg(feature = "download")]
     pub fn cache_lang_model(lang_code: LanguageCode) -> Result<PathBuf, cached_path::Error>{
-        cached_path(
-            format!("https://raw.githubusercontent.com/oneshinyboi/swipeType/v0.1.10/crates/super-swipe-engine/assets/{}.bin", lang_code.to_string()).as_str()
-        )
+        cached_path(ModelSource::default().model_url(lang_code).unwrap().as_str())
     }
+
+    /// Loads the model for `lang_code`.
+    ///
+    /// Models compiled in with the `embedded-models` feature are used first, then the
+    /// published model is downloaded if the `download` feature is enabled. A language the
+    /// mirror has no model for fails with [`EngineLoadError::UnsupportedLanguage`]. Use
+    /// [`SwipeEngine::from_source`], [`SwipeEngine::from_path`] or [`SwipeEngine::from_bytes`]
+    /// to load any other model, or [`SwipeEngine::builder`] to change the engine settings.
     pub fn new(lang_code: LanguageCode, layout: Option<HashMap<char, Point>>) -> Result<Self, EngineLoadError> {
+        let (header, model) = Self::load_language(lang_code)?;
+        Self::from_dictionary(header, model, layout.map(|layout| vec![Layer::base(unit_key_rects(&layout))]), EngineConfig::default())
+    }
 
-        match Self::cache_lang_model(lang_code) {
-            Ok(model_path) => {
-               println!("{:?}", model_path);
-                match fs::read(model_path) {
-                    Ok(bytes) =>
-                        match bincode::decode_from_slice::<Dictionary, Configuration>(&bytes, bincode::config::standard()) {
-                        Ok((model, _len)) => {
-                            let mut engine = Self {
-                                dictionary: model,
-                                layout: layout.unwrap_or_else(get_keyboard_layout),
-                                pop_weight: 0.25,
-                                bigram_weight: 0.5,
-                                by_first_letter: HashMap::new(),
-                                word_paths: Vec::new(),
-                            };
-                            engine.build_index();
-                            Ok(engine)
-                        }
-                        Err(e) => Err(EngineLoadError::ModelFileError(format!("Failed to decode dictionary for {}: {}", lang_code.to_string(), e))),
-                    }
-                    Err(read_err) => Err(EngineLoadError::ModelFileError(format!("Error reading model file: {}", read_err)))
-                }
-            }
-            Err(e) => Err(EngineLoadError::ModelFileError(format!("Could not access cached model file: {}", e.to_string()).to_string()))
+    /// Loads the model for `lang_code` from a mirror or local directory, verifying its checksum.
+    pub fn from_source(lang_code: LanguageCode, source: &ModelSource, layout: Option<HashMap<char, Point>>) -> Result<Self, EngineLoadError> {
+        let (header, model) = Self::load_source(lang_code, source)?;
+        Self::from_dictionary(header, model, layout.map(|layout| vec![Layer::base(unit_key_rects(&layout))]), EngineConfig::default())
+    }
+
+    /// Loads a compiled model (`{lang}.bin`) from the local filesystem.
+    pub fn from_path(path: &Path, layout: Option<HashMap<char, Point>>) -> Result<Self, EngineLoadError> {
+        let (header, model) = Self::load_path(path)?;
+        Self::from_dictionary(header, model, layout.map(|layout| vec![Layer::base(unit_key_rects(&layout))]), EngineConfig::default())
+    }
+
+    /// Decodes a compiled model from memory. Legacy models without a header are still accepted.
+    pub fn from_bytes(bytes: &[u8], layout: Option<HashMap<char, Point>>) -> Result<Self, EngineLoadError> {
+        let (header, model) = Self::load_bytes(bytes)?;
+        Self::from_dictionary(header, model, layout.map(|layout| vec![Layer::base(unit_key_rects(&layout))]), EngineConfig::default())
+    }
+
+    pub fn builder() -> SwipeEngineBuilder {
+        SwipeEngineBuilder::new()
+    }
+
+    fn load_language(lang_code: LanguageCode) -> Result<(ModelHeader, Dictionary), EngineLoadError> {
+        #[cfg(feature = "embedded-models")]
+        if let Some(bytes) = embedded::embedded_model(lang_code.as_ref()) {
+            let (mut header, model) = Self::load_bytes(bytes)?;
+            resolve_language(&mut header, lang_code)?;
+            return Ok((header, model));
         }
 
+        Self::load_source(lang_code, &ModelSource::default())
+    }
+
+    fn load_source(lang_code: LanguageCode, source: &ModelSource) -> Result<(ModelHeader, Dictionary), EngineLoadError> {
+        let (mut header, model) = Self::load_bytes(&source.load(lang_code)?)?;
+        resolve_language(&mut header, lang_code)?;
+        Ok((header, model))
+    }
 
+    fn load_path(path: &Path) -> Result<(ModelHeader, Dictionary), EngineLoadError> {
+        let bytes = fs::read(path).map_err(|source| EngineLoadError::Io { path: path.to_path_buf(), source })?;
+        Self::load_bytes(&bytes)
+    }
+
+    fn load_bytes(bytes: &[u8]) -> Result<(ModelHeader, Dictionary), EngineLoadError> {
+        Ok(decode_model(bytes)?)
+    }
+
+    fn from_dictionary(header: ModelHeader, dictionary: Dictionary, layers: Option<Vec<Layer>>, config: EngineConfig) -> Result<Self, EngineLoadError> {
+        let layers = match layers {
+            Some(layers) => layers,
+            None => default_layout(&header).definition().layer_keys()?,
+        };
+        let layout = layers[0].centres();
+        validate_layout(&layout)?;
+        config.unknown_chars.validate(&layout).map_err(EngineLoadError::InvalidConfig)?;
+
+        let folding = config.folding.table(&header.language);
+        let mut engine = Self {
+            header,
+            dictionary,
+            layout,
+            layers,
+            config,
+            folding,
+            by_first_letter: HashMap::new(),
+            word_keys: Vec::new(),
+            paths: Vec::new(),
+            path_words: Vec::new(),
+            path_word_starts: Vec::new(),
+            path_summaries: Vec::new(),
+            tries: HashMap::new(),
+            ann: HashMap::new(),
+            path_key_sizes: Vec::new(),
+        };
+        engine.build_index();
+        Ok(engine)
+    }
+
+    /// Header of the loaded model. Legacy models report format version 0, and no language unless
+    /// they were loaded for a language code, which then picks their layout and folding table.
+    pub fn model_header(&self) -> &ModelHeader {
+        &self.header
+    }
+
+    /// Settings the engine was built with.
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
     }
 
     /// Higher values favor common words more heavily in the scoring function.
-    pub fn set_pop_weight(&mut self, weight: f64) {
-        self.pop_weight = weight;
+    /// The weight must be finite and non-negative; otherwise the engine is left unchanged.
+    pub fn set_pop_weight(&mut self, weight: f64) -> Result<(), EngineLoadError> {
+        self.update_config(EngineConfig { pop_weight: weight, ..self.config.clone() })
     }
 
     /// Higher values favor words that are more likely to follow the previous word.
-    pub fn set_bigram_weight(&mut self, weight: f64) {
-        self.bigram_weight = weight;
+    /// The weight must be finite and non-negative; otherwise the engine is left unchanged.
+    pub fn set_bigram_weight(&mut self, weight: f64) -> Result<(), EngineLoadError> {
+        self.update_config(EngineConfig { bigram_weight: weight, ..self.config.clone() })
+    }
+
+    fn update_config(&mut self, config: EngineConfig) -> Result<(), EngineLoadError> {
+        config.validate()?;
+        self.config = config;
+        Ok(())
     }
 
     fn build_index(&mut self) {
         self.by_first_letter.clear();
-        self.word_paths.clear();
-        self.word_paths.reserve(self.dictionary.words.len());
-        for (idx, word) in self.dictionary.words.iter().enumerate() {
-            if let Some(first) = word.chars().next() {
-                self.by_first_letter
-                    .entry(first.to_ascii_lowercase())
-                    .or_insert_with(Vec::new)
-                    .push(idx);
+        self.word_keys.clear();
+        self.paths.clear();
+        self.path_words.clear();
+        self.path_word_starts.clear();
+        self.path_summaries.clear();
+        self.tries.clear();
+        self.ann.clear();
+        self.path_key_sizes.clear();
+        // all keys first, so they stay together in memory for the penalties
+        self.word_keys = self.dictionary.words.iter().map(|word| self.key_points(word)).collect();
+        // paths by their bucket and template hash, checked against the stored path on a match
+        let mut path_ids: HashMap<(Option<char>, u64), usize> = HashMap::with_capacity(self.dictionary.words.len());
+        let mut word_path_ids = Vec::with_capacity(self.dictionary.words.len());
+        for keys in &self.word_keys {
+            // words are bucketed by the key their path starts on, so "'til" goes with "t"
+            let first = keys.first().and_then(|p| nearest_key(p, &self.layout));
+            let raw_path = self.keys_path(keys);
+            let word_path = self.prepare_path(&raw_path);
+
+            // paths are only shared within a bucket, so each word is still a candidate for the same swipes
+            let path_id = self.paths.len();
+            match path_ids.entry((first, self.template_hash(&word_path))) {
+                Entry::Occupied(entry) if self.same_template(&self.paths[*entry.get()], &word_path) => {
+                    word_path_ids.push(*entry.get());
+                    continue;
+                }
+                // a hash collision only keeps the paths apart
+                Entry::Occupied(_) => {}
+                Entry::Vacant(entry) => {
+                    entry.insert(path_id);
+                }
+            }
+            if let Some(first) = first {
+                self.by_first_letter.entry(first).or_default().push(path_id);
+            }
+            if self.config.key_model != KeyModel::Centre {
+                self.path_key_sizes.push(word_path.iter().map(|p| self.key_size_at(p)).collect());
+            }
+            self.path_summaries.push(PathSummary::new(&word_path));
+            self.paths.push(word_path);
+            word_path_ids.push(path_id);
+        }
+
+        self.path_word_starts = vec![0; self.paths.len() + 1];
+        for &path_id in &word_path_ids {
+            self.path_word_starts[path_id + 1] += 1;
+        }
+        for i in 1..self.path_word_starts.len() {
+            self.path_word_starts[i] += self.path_word_starts[i - 1];
+        }
+        self.path_words = (0..word_path_ids.len()).collect();
+        // stable, so the words of a path stay in dictionary order
+        self.path_words.sort_by_key(|&idx| word_path_ids[idx]);
+
+        if self.uses_trie() {
+            for (first, path_ids) in &self.by_first_letter {
+                let mut trie = PathTrie::new();
+                for &path_id in path_ids {
+                    let log_freq = self
+                        .path_words(path_id)
+                        .iter()
+                        .map(|&idx| self.word_probabilities(&self.dictionary.words[idx], None).0)
+                        .fold(f64::NEG_INFINITY, f64::max);
+                    trie.insert(path_id, &self.paths[path_id], log_freq);
+                }
+                self.tries.insert(*first, trie);
+            }
+        }
+
+        if let Retrieval::Ann { points, .. } = self.config.retrieval {
+            for (first, path_ids) in &self.by_first_letter {
+                let vectors: Vec<f64> = path_ids.iter().flat_map(|&path_id| embed_path(&self.paths[path_id], points)).collect();
+                self.ann.insert(*first, VpTree::new(path_ids, &vectors, 2 * points));
             }
-            let raw_path = get_word_path(word, &self.layout);
-            self.word_paths.push(simplify_path(&raw_path));
         }
     }
 
+    /// Words typed along path `path_id`.
+    fn path_words(&self, path_id: usize) -> &[usize] {
+        &self.path_words[self.path_word_starts[path_id]..self.path_word_starts[path_id + 1]]
+    }
+
+    /// Identifies the points of a template: exact coordinates or, with a
+    /// [`EngineConfig::template_tolerance`], the cells of a grid that size they fall in.
+    fn template_cells<'p>(&self, path: &'p [Point]) -> impl Iterator<Item = (u64, u64)> + 'p {
+        let tolerance = self.config.template_tolerance;
+        path.iter().map(move |p| {
+            if tolerance == 0.0 {
+                (p.x.to_bits(), p.y.to_bits())
+            } else {
+                ((p.x / tolerance).round() as i64 as u64, (p.y / tolerance).round() as i64 as u64)
+            }
+        })
+    }
+
+    fn template_hash(&self, path: &[Point]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.template_cells(path).for_each(|cell| cell.hash(&mut hasher));
+        hasher.finish()
+    }
+
+    /// Whether two word paths can share a template.
+    fn same_template(&self, a: &[Point], b: &[Point]) -> bool {
+        a.len() == b.len() && self.template_cells(a).eq(self.template_cells(b))
+    }
+
+    /// Width and height of the key closest to `point`.
+    fn key_size_at(&self, point: &Point) -> (f64, f64) {
+        nearest_key(point, &self.layout)
+            .and_then(|c| self.layers[0].keys.get(&c))
+            .map_or((1.0, 1.0), |rect| (rect.width, rect.height))
+    }
+
+    /// Rectangles of the keys of the main layer, by the character they type.
+    pub fn key_rects(&self) -> &HashMap<char, KeyRect> {
+        &self.layers[0].keys
+    }
+
+    /// Layers of the layout, the main one first.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer_names(&self) -> Vec<&str> {
+        self.layers.iter().map(|layer| layer.name.as_str()).collect()
+    }
+
+    /// The key a single tap at `point` most likely meant, under the configured key model.
+    pub fn correct_tap(&self, point: &Point) -> Option<char> {
+        most_likely_key(point, &self.layers[0].keys, &self.config.key_model)
+    }
+
     pub fn word_count(&self) -> usize {
         self.dictionary.words.len()
     }
 
+    /// Number of distinct word paths, each matched once for all the words that share it.
+    pub fn path_count(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Adds words the model doesn't have, such as "2nd" or emoticons typed on a symbol layer,
+    /// with the given log frequency. Words already in the dictionary are left as they are.
+    pub fn add_words(&mut self, words: &[&str], log_freq: f64) {
+        let mut added = false;
+        for word in words {
+            added |= add_word(&mut self.dictionary, word, log_freq);
+        }
+        if added {
+            self.build_index();
+        }
+    }
+
     /// Input string should be the sequence of characters the swipe path passes through.
     /// Returns predictions sorted by score.
     /// previous_word will be ignored if lib was compiled without use-pair-counts feature
     pub fn predict(&self, swipe_input: &str, previous_word: Option<&str>, limit: usize) -> Vec<Prediction> {
-        let raw_input_path = get_word_path(swipe_input, &self.layout);
-        if raw_input_path.is_empty() {
-            return vec![];
+        self.predict_with_stats(swipe_input, previous_word, limit).0
+    }
+
+    /// Like [`SwipeEngine::predict`], also returning how many words were skipped by the lower bounds.
+    pub fn predict_with_stats(&self, swipe_input: &str, previous_word: Option<&str>, limit: usize) -> (Vec<Prediction>, SearchStats) {
+        match self.key_query(swipe_input) {
+            Some(query) => self.rank_candidates(&query, previous_word, limit),
+            None => (vec![], SearchStats::default()),
         }
+    }
 
-        let input_path = simplify_path(&raw_input_path);
-        let input_len = input_path.len() as f64;
+    /// The query for a key input, `None` if none of its keys are on the layout.
+    fn key_query(&self, swipe_input: &str) -> Option<SwipeQuery> {
+        let keys = self.key_points(swipe_input);
+        let (&first_key, &last_key) = (keys.first()?, keys.last()?);
+        let input_path = self.prepare_path(&self.keys_path(&keys));
 
-        let first_char = match swipe_input.chars().next() {
-            Some(c) => c.to_ascii_lowercase(),
-            None => return vec![],
+        Some(SwipeQuery {
+            path: input_path,
+            first_key: Some(first_key),
+            first_pt: first_key,
+            start_radius: self.config.start_radius,
+            start_confidence: 1.0,
+            last_key: Some(last_key),
+            last_pt: last_key,
+            end_confidence: 1.0,
+            hits: vec![],
+            repeated_keys: Some(repeated_keys(&keys)),
+        })
+    }
+
+    /// Matches a raw touch trace, in the same coordinate space as the layout, against the dictionary.
+    /// The trace is resampled like the word paths, so curves between keys and off-centre
+    /// touches contribute to the score. Returns predictions sorted by score.
+    pub fn predict_path(&self, points: &[Point], previous_word: Option<&str>, limit: usize) -> Vec<Prediction> {
+        self.predict_path_with_stats(points, previous_word, limit).0
+    }
+
+    /// Like [`SwipeEngine::predict_path`], also returning how many words were skipped by the lower bounds.
+    pub fn predict_path_with_stats(&self, points: &[Point], previous_word: Option<&str>, limit: usize) -> (Vec<Prediction>, SearchStats) {
+        let (Some(first_pt), Some(last_pt)) = (points.first(), points.last()) else {
+            return (vec![], SearchStats::default());
         };
-        let last_char = swipe_input.chars().last().unwrap().to_ascii_lowercase();
-        let last_char_pt = self
-            .layout
-            .get(&last_char)
-            .cloned()
-            .unwrap_or(Point { x: 0.0, y: 0.0 });
 
-        let candidate_indices = match self.by_first_letter.get(&first_char) {
-            Some(indices) => indices,
-            None => return vec![],
+        let query = SwipeQuery {
+            path: self.trace_input_path(points),
+            first_key: None,
+            first_pt: *first_pt,
+            start_radius: self.config.start_radius,
+            start_confidence: 1.0,
+            last_key: None,
+            last_pt: *last_pt,
+            end_confidence: 1.0,
+            hits: vec![],
+            repeated_keys: None,
         };
+        self.rank_candidates(&query, previous_word, limit)
+    }
 
-        let window = (input_path.len() / 2).max(10);
-        let mut best_score = f64::INFINITY;
+    /// Like [`SwipeEngine::predict_path`], for a recorded trace of timestamped touch samples.
+    /// Dwells and sharp turns in the trace are used as soft constraints: words are penalized
+    /// by how far their keys are from each detected [`KeyHit`]. The first and last key are soft
+    /// too: the less clearly the trace starts or ends on a key, the more neighbouring keys words
+    /// may start on and the smaller the start and end penalties.
+    pub fn predict_trace(&self, trace: &SwipeTrace, previous_word: Option<&str>, limit: usize) -> Vec<Prediction> {
+        let hits = self.key_hits(trace);
+        let (Some(first_sample), Some(last_sample)) = (trace.samples.first(), trace.samples.last()) else {
+            return vec![];
+        };
 
-        let mut candidates: Vec<(String, f64, f64, f64)> = candidate_indices
-            .iter()
-            .filter_map(|&idx| {
-                let w = &self.dictionary.words[idx];
-
-                let word_last_char = w.chars().last().unwrap().to_lowercase().next().unwrap();
-                let mut end_penalty = 0.0;
-                if word_last_char != last_char {
-                    if let Some(word_last_pt) = self.layout.get(&word_last_char) {
-                        end_penalty = euclidean_dist(&last_char_pt, word_last_pt) * 0.5;
-                    } else {
-                        end_penalty = 50.0;
-                    }
+        // the start and end hits soften the start and end constraints when the trace begins or
+        // ends between keys, instead of committing to the closest one
+        let confidence = |kind: KeyHitKind| hits.iter().find(|hit| hit.kind == kind).map_or(1.0, |hit| hit.confidence);
+        let start_confidence = confidence(KeyHitKind::Start);
+        let query = SwipeQuery {
+            path: self.trace_input_path(&trace.points()),
+            first_key: None,
+            first_pt: first_sample.point(),
+            start_radius: self.config.start_radius + (1.0 - start_confidence),
+            start_confidence,
+            last_key: None,
+            last_pt: last_sample.point(),
+            end_confidence: confidence(KeyHitKind::End),
+            hits: hits.iter().filter(|hit| matches!(hit.kind, KeyHitKind::Dwell | KeyHitKind::Corner)).cloned().collect(),
+            repeated_keys: None,
+        };
+        self.rank_candidates(&query, previous_word, limit).0
+    }
+
+    /// Like [`SwipeEngine::predict_path`], for points in screen pixels.
+    pub fn predict_pixels(&self, pixels: &[Point], mapping: &ScreenMapping, previous_word: Option<&str>, limit: usize) -> Vec<Prediction> {
+        self.predict_path(&mapping.map_points(pixels), previous_word, limit)
+    }
+
+    /// Like [`SwipeEngine::predict_trace`], for a trace in screen pixels.
+    pub fn predict_trace_pixels(&self, trace: &SwipeTrace, mapping: &ScreenMapping, previous_word: Option<&str>, limit: usize) -> Vec<Prediction> {
+        self.predict_trace(&mapping.map_trace(trace), previous_word, limit)
+    }
+
+    /// Predicts a swipe drawn while layer `layer` is shown, such as numbers or symbols.
+    ///
+    /// Vocabulary words are matched as if the swipe started on the switch key to the layer, so
+    /// words that switch back to letters midway are found too. The key sequence the trace passes
+    /// through on that layer is ranked with them, scored by the distance from the swipe to its
+    /// path like a word that isn't in the dictionary.
+    /// Returns an empty list if there is no such layer.
+    pub fn predict_trace_on_layer(&self, trace: &SwipeTrace, layer: &str, previous_word: Option<&str>, limit: usize) -> Vec<Prediction> {
+        let Some(idx) = self.layer_index(layer) else {
+            return vec![];
+        };
+        if idx == 0 {
+            return self.predict_trace(trace, previous_word, limit);
+        }
+
+        let mut points = self.switch_path(0, idx).unwrap_or_default();
+        points.extend(trace.points());
+        let words = self.predict_path(&points, previous_word, limit);
+        let mut candidates: Vec<(String, f64, f64, f64)> =
+            words.into_iter().map(|p| (p.word, p.score, p.freq, p.bigram_prob.unwrap_or(0.0))).collect();
+
+        let keys = key_string(&detect_key_hits(trace, &self.layers[idx].centres(), &self.config.key_hits));
+        if !keys.is_empty() && candidates.iter().all(|(word, ..)| *word != keys) {
+            let input_path = self.trace_input_path(&points);
+            let key_path = self.prepare_path(&self.keys_path(&self.layered_key_points(&keys, 0).0));
+            let window = self.config.window(input_path.len());
+            let dist = self.config.distance.distance(&input_path, &key_path, window, f64::INFINITY);
+            if dist.is_finite() {
+                let (freq, bigram_prob) = self.word_probabilities(&keys, previous_word);
+                candidates.push((keys, dist / input_path.len() as f64, freq, bigram_prob));
+            }
+        }
+        self.sorted_predictions(candidates, limit)
+    }
+
+    /// Area covered by the letter keys of the base layer, in key units. This is the region
+    /// [`SwipeEngine::screen_mapping`] stretches over the keyboard's letter rows on screen.
+    pub fn layout_bounds(&self) -> Bounds {
+        Bounds::of_letter_keys(&self.layers[0].keys)
+    }
+
+    /// Maps a keyboard drawn over `screen`, in pixels, onto the layout.
+    pub fn screen_mapping(&self, screen: Bounds) -> ScreenMapping {
+        ScreenMapping::fit(screen, &self.layout_bounds())
+    }
+
+    /// Keys the user likely meant to hit in `trace`, useful to debug a gesture.
+    pub fn key_hits(&self, trace: &SwipeTrace) -> Vec<KeyHit> {
+        detect_key_hits(trace, &self.layout, &self.config.key_hits)
+    }
+
+    /// Starts a [`SwipeSession`] that predicts while the swipe is still being drawn.
+    pub fn session(&self, previous_word: Option<&str>) -> SwipeSession<'_> {
+        SwipeSession::new(self, previous_word)
+    }
+
+    /// Centres of the keys `word` is typed with, after folding accented letters and
+    /// applying the unknown character policy.
+    fn key_points(&self, word: &str) -> Vec<Point> {
+        self.layered_key_points(word, 0).0
+    }
+
+    /// Like [`SwipeEngine::key_points`], starting on layer `start`. Characters on other layers
+    /// are reached through their switch keys. Also returns the layer the word ends on.
+    fn layered_key_points(&self, word: &str, start: usize) -> (Vec<Point>, usize) {
+        let folded = self.folding.fold(word, &self.layout);
+        if self.layers.len() == 1 {
+            return (self.config.unknown_chars.key_points(&folded, &self.layout), 0);
+        }
+
+        let mut current = start;
+        let mut points = Vec::new();
+        for c in folded.chars() {
+            if let Some(rect) = self.layers[current].keys.get(&c) {
+                points.push(rect.centre);
+                continue;
+            }
+            let target = self.layers.iter().position(|layer| layer.keys.contains_key(&c));
+            match target.and_then(|target| Some((target, self.switch_path(current, target)?))) {
+                Some((target, switches)) => {
+                    points.extend(switches);
+                    points.push(self.layers[target].keys[&c].centre);
+                    current = target;
                 }
+                None => points.extend(self.config.unknown_chars.key_points(&c.to_string(), &self.layout)),
+            }
+        }
+        (points, current)
+    }
+
+    /// Switch keys to press to get from layer `from` to layer `to`, directly or through the main layer.
+    fn switch_path(&self, from: usize, to: usize) -> Option<Vec<Point>> {
+        if let Some(direct) = self.layers[from].switch_to(to) {
+            return Some(vec![direct.centre]);
+        }
+        let to_base = if from == 0 { None } else { Some(self.layers[from].switch_to(0)?.centre) };
+        let from_base = self.layers[0].switch_to(to)?.centre;
+        Some(to_base.into_iter().chain(std::iter::once(from_base)).collect())
+    }
 
-                let cutoff = best_score * input_len;
-                let word_path = &self.word_paths[idx];
-                let dist = dtw_distance_fast(&input_path, word_path, window, cutoff);
+    fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
 
-                if dist == f64::INFINITY {
-                    return None;
+    /// Path through `keys`, with a loop on each repeated key.
+    fn keys_path(&self, keys: &[Point]) -> Vec<Point> {
+        path_through_keys(keys, self.config.resample_step, self.config.double_letter_loop_radius)
+    }
+
+    fn trace_input_path(&self, points: &[Point]) -> Vec<Point> {
+        self.prepare_path(&resample_path(points, self.config.resample_step))
+    }
+
+    /// Simplifies and preprocesses a path the same way for inputs and word paths.
+    fn prepare_path(&self, raw_path: &[Point]) -> Vec<Point> {
+        let simplified = simplify_path_with_threshold(raw_path, self.config.simplify_threshold);
+        self.config.preprocessing.apply(&simplified)
+    }
+
+    /// Sum of the distances from each hit to the closest key of `word`, weighted by confidence.
+    fn key_hit_penalty(&self, word_idx: usize, hits: &[KeyHit]) -> f64 {
+        if hits.is_empty() || self.config.key_hit_weight == 0.0 {
+            return 0.0;
+        }
+        hits.iter()
+            .map(|hit| {
+                let closest = self.word_keys[word_idx].iter().map(|key| euclidean_dist(&hit.point, key)).fold(f64::INFINITY, f64::min);
+                if closest.is_finite() { closest * hit.confidence } else { 0.0 }
+            })
+            .sum::<f64>()
+            * self.config.key_hit_weight
+    }
+
+    /// Distance between `input_path` and path `path_id` under the configured metric.
+    /// Plain DTW is scored with the key model.
+    fn path_distance(&self, input_path: &[Point], path_id: usize, window: usize, cutoff: f64) -> f64 {
+        let word_path = &self.paths[path_id];
+        match (&self.config.distance, self.config.key_model) {
+            (DistanceMetric::Dtw, KeyModel::Centre) => dtw_distance_fast(input_path, word_path, window, cutoff),
+            (DistanceMetric::Dtw, _) => dtw_banded_with(input_path.len(), word_path.len(), window, cutoff, |i, j| self.point_cost(&input_path[i], path_id, j)),
+            (metric, _) => metric.distance(input_path, word_path, window, cutoff),
+        }
+    }
+
+    /// Cost of aligning `point` with point `j` of path `path_id`.
+    fn point_cost(&self, point: &Point, path_id: usize, j: usize) -> f64 {
+        let template = &self.paths[path_id][j];
+        match self.config.key_model {
+            KeyModel::Centre => euclidean_dist(point, template),
+            model => {
+                let (width, height) = self.path_key_sizes[path_id][j];
+                model.cost(point, template, width, height)
+            }
+        }
+    }
+
+    /// Penalizes each repeated key that only one of `word` and the key input has.
+    fn double_letter_penalty(&self, word_idx: usize, input_repeated: Option<&[Point]>) -> f64 {
+        let Some(input_repeated) = input_repeated else {
+            return 0.0;
+        };
+        let mut unmatched = input_repeated.to_vec();
+        let mut mismatches = 0;
+        for key in repeated_keys(&self.word_keys[word_idx]) {
+            match unmatched.iter().position(|&c| c == key) {
+                Some(pos) => {
+                    unmatched.swap_remove(pos);
                 }
+                None => mismatches += 1,
+            }
+        }
+        (mismatches + unmatched.len()) as f64 * self.config.double_letter_penalty
+    }
+
+    /// Scores the words starting near the query's first point against its path.
+    ///
+    /// Each distinct path is matched once for all its words. Words that can't enter the best
+    /// `limit` once their penalties and frequency are counted are dropped as early as possible:
+    /// by giving up on their DTW, with lower bounds in a linear search, or a branch at a time
+    /// in a trie. The predictions don't depend on the order the paths are scored in, so both
+    /// strategies return the same ones.
+    fn rank_candidates(&self, query: &SwipeQuery, previous_word: Option<&str>, limit: usize) -> (Vec<Prediction>, SearchStats) {
+        self.rank_candidates_with(query, previous_word, limit, self.has_lower_bounds())
+    }
 
-                let score = (dist + end_penalty) / input_len;
-                if score < best_score {
-                    best_score = score;
+    /// Like [`SwipeEngine::rank_candidates`], skipping the lower bounds in a linear search unless
+    /// `lower_bounds` is set, so they can be checked against the unpruned search.
+    fn rank_candidates_with(&self, query: &SwipeQuery, previous_word: Option<&str>, limit: usize, lower_bounds: bool) -> (Vec<Prediction>, SearchStats) {
+        let input_path = &query.path;
+        let window = self.config.window(input_path.len());
+        let mut ranking = Ranking::new(self, query, previous_word, limit);
+        let mut stats = SearchStats::default();
+
+        let start_keys = self.start_keys(&query.first_pt, query.start_radius);
+        if self.uses_trie() {
+            for trie in start_keys.iter().filter_map(|c| self.tries.get(c)) {
+                stats.candidates += trie.path_count();
+                let cost = |i: usize, path_id: usize, j: usize| self.point_cost(&input_path[i], path_id, j);
+                stats.pruned_by_prefix += trie.search(input_path.len(), window, cost, &mut ranking);
+            }
+            stats.distance_runs = stats.candidates - stats.pruned_by_prefix;
+            return (ranking.into_predictions(), stats);
+        }
+
+        let candidate_paths = self.retrieve(query);
+        stats.candidates = candidate_paths.len();
+        let bounds = lower_bounds.then(|| QueryBounds::new(input_path, window));
+        for path_id in candidate_paths {
+            ranking.load_words(path_id);
+            let threshold = ranking.path_threshold();
+            if let Some(bounds) = &bounds {
+                let summary = &self.path_summaries[path_id];
+                if bounds.length_mismatch(summary) {
+                    stats.pruned_by_length += 1;
+                    continue;
+                }
+                if bounds.lower_bound(summary, &self.paths[path_id], threshold) > threshold {
+                    stats.pruned_by_bound += 1;
+                    continue;
                 }
+            }
+
+            stats.distance_runs += 1;
+            let dist = self.path_distance(input_path, path_id, window, threshold);
+            ranking.add(dist);
+        }
+
+        (ranking.into_predictions(), stats)
+    }
+
+    fn uses_trie(&self) -> bool {
+        self.config.search == SearchStrategy::Trie && self.config.distance == DistanceMetric::Dtw && self.config.retrieval == Retrieval::Exhaustive
+    }
+
+    /// Paths to score for the query: those starting near it or, with [`Retrieval::Ann`], the ones
+    /// among them whose embedding is closest to the query's.
+    fn retrieve(&self, query: &SwipeQuery) -> Vec<usize> {
+        let Retrieval::Ann { candidates, points } = self.config.retrieval else {
+            return self.start_candidates(&query.first_pt, query.start_radius);
+        };
+        let embedding = embed_path(&query.path, points);
+        let mut nearest: Vec<(f64, usize)> = self
+            .start_keys(&query.first_pt, query.start_radius)
+            .iter()
+            .filter_map(|c| self.ann.get(c))
+            .flat_map(|tree| tree.nearest(&embedding, candidates))
+            .collect();
+        nearest.sort_by(|a, b| a.0.total_cmp(&b.0));
+        nearest.truncate(candidates);
+        nearest.into_iter().map(|(_, path_id)| path_id).collect()
+    }
+
+    /// Whether [`QueryBounds`] hold for the configured distance: they assume DTW with
+    /// Euclidean point costs.
+    fn has_lower_bounds(&self) -> bool {
+        self.config.distance == DistanceMetric::Dtw && self.config.key_model == KeyModel::Centre
+    }
+
+    /// Paths starting on a key near `first_pt`: the closest key, and the keys within `radius`
+    /// of it, usually [`EngineConfig::start_radius`], so a swipe that starts on a neighbouring
+    /// key still finds the intended word.
+    fn start_candidates(&self, first_pt: &Point, radius: f64) -> Vec<usize> {
+        self.start_keys(first_pt, radius).iter().filter_map(|c| self.by_first_letter.get(c)).flatten().copied().collect()
+    }
+
+    /// Keys whose paths are candidates for a swipe starting at `first_pt`, see [`SwipeEngine::start_candidates`].
+    fn start_keys(&self, first_pt: &Point, radius: f64) -> Vec<char> {
+        let Some(closest) = nearest_key(first_pt, &self.layout) else {
+            return vec![];
+        };
+        let mut keys: Vec<char> = self
+            .layout
+            .iter()
+            .filter(|(c, key)| **c == closest || euclidean_dist(first_pt, key) <= radius)
+            .map(|(c, _)| *c)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
 
-                let word_info = self.dictionary.word_info.get(&w.as_str().to_lowercase());
-                let mut word_freq = 0.0;
-                let mut bigram_probability: f64 = 0.0;
-
-                if let Some(word_info) = word_info {
-                    word_freq = word_info.log_freq;
-                    if let Some(previous_word) = previous_word {
-                        let previous_word_lowercase = previous_word.to_lowercase();
-                        if let Some(pair_counts) = &self.dictionary.pair_counts {
-                            if let Some(pair_count_map) = pair_counts.get(&previous_word_lowercase) {
-                                let bigram_count = pair_count_map.get(&w.as_str().to_lowercase()).unwrap_or(&0u32);
-                                if let Some(previous_word_count) = self.dictionary.word_info.get(&previous_word_lowercase) {
-                                    bigram_probability = *bigram_count as f64 / previous_word_count.count as f64;
-                                }
-                            }
+    /// The start counterpart of [`SwipeEngine::end_penalty`]: words starting on `first_key`
+    /// get no penalty, all others are penalized by the distance from `first_pt` to their first
+    /// key, scaled by `confidence`.
+    fn start_penalty(&self, word_idx: usize, first_key: Option<&Point>, first_pt: &Point, confidence: f64) -> f64 {
+        match self.word_keys[word_idx].first() {
+            Some(word_first_key) if Some(word_first_key) == first_key => 0.0,
+            Some(word_first_key) => euclidean_dist(first_pt, word_first_key) * self.config.start_penalty_factor * confidence,
+            None => 0.0,
+        }
+    }
+
+    /// Words ending on `last_key` get no end penalty, all others are penalized by the
+    /// distance from `last_pt` to their last key, scaled by `confidence`, how sure the input
+    /// is of its last key. Words without keys on the layout get the unknown key penalty.
+    fn end_penalty(&self, word_idx: usize, last_key: Option<&Point>, last_pt: &Point, confidence: f64) -> f64 {
+        match self.word_keys[word_idx].last() {
+            Some(word_last_key) if Some(word_last_key) == last_key => 0.0,
+            Some(word_last_key) => euclidean_dist(last_pt, word_last_key) * self.config.end_penalty_factor * confidence,
+            None => self.config.unknown_key_penalty,
+        }
+    }
+
+    /// Returns the log frequency of `word` and the probability that it follows `previous_word`.
+    fn word_probabilities(&self, word: &str, previous_word: Option<&str>) -> (f64, f64) {
+        let word_info = self.dictionary.word_info.get(&word.to_lowercase());
+        let mut word_freq = 0.0;
+        let mut bigram_probability: f64 = 0.0;
+
+        if let Some(word_info) = word_info {
+            word_freq = word_info.log_freq;
+            if let Some(previous_word) = previous_word {
+                let previous_word_lowercase = previous_word.to_lowercase();
+                if let Some(pair_counts) = &self.dictionary.pair_counts {
+                    if let Some(pair_count_map) = pair_counts.get(&previous_word_lowercase) {
+                        let bigram_count = pair_count_map.get(&word.to_lowercase()).unwrap_or(&0u32);
+                        if let Some(previous_word_count) = self.dictionary.word_info.get(&previous_word_lowercase) {
+                            bigram_probability = *bigram_count as f64 / previous_word_count.count as f64;
                         }
                     }
                 }
+            }
+        }
+        (word_freq, bigram_probability)
+    }
 
-                Some((w.clone(), score, word_freq, bigram_probability))
-            })
-            .collect();
+    /// Highest probability of any word following `previous_word`.
+    fn max_bigram_probability(&self, previous_word: &str) -> f64 {
+        let previous_word = previous_word.to_lowercase();
+        let (Some(pair_counts), Some(previous_info)) = (&self.dictionary.pair_counts, self.dictionary.word_info.get(&previous_word)) else {
+            return 0.0;
+        };
+        let max_count = pair_counts.get(&previous_word).and_then(|counts| counts.values().max()).copied().unwrap_or(0);
+        max_count as f64 / previous_info.count as f64
+    }
+
+    /// Path score adjusted for how likely the word is; lower is better.
+    fn combined_score(&self, score: f64, freq: f64, bigram_prob: f64) -> f64 {
+        score - freq * self.config.pop_weight - bigram_prob * self.config.bigram_weight
+    }
 
+    /// Sorts `(word, score, freq, bigram_prob)` candidates by their combined score.
+    fn sorted_predictions(&self, mut candidates: Vec<(String, f64, f64, f64)>, limit: usize) -> Vec<Prediction> {
         candidates.sort_by(|a, b| {
-            let combined_a = a.1 - a.2 * self.pop_weight - a.3 * self.bigram_weight;
-            let combined_b = b.1 - b.2 * self.pop_weight - b.3 * self.bigram_weight;
-            //println!("{}, {}", a.3, b.3);
+            let combined_a = self.combined_score(a.1, a.2, a.3);
+            let combined_b = self.combined_score(b.1, b.2, b.3);
             combined_a
                 .partial_cmp(&combined_b)
                 .unwrap_or(std::cmp::Ordering::Equal)
@@ -211,6 +867,144 @@ impl SwipeEngine {
     }
 }
 
+/// The best words found so far while ranking candidates.
+struct Ranking<'a> {
+    engine: &'a SwipeEngine,
+    query: &'a SwipeQuery,
+    previous_word: Option<&'a str>,
+    limit: usize,
+    /// Combined scores of the best `limit` words so far, in ascending order.
+    top: Vec<f64>,
+    candidates: Vec<(String, f64, f64, f64)>,
+    /// Highest bigram probability of any word after `previous_word`.
+    max_bigram_prob: f64,
+    /// The words of the path being scored, with their penalty, log frequency and bigram probability.
+    words: Vec<(usize, f64, f64, f64)>,
+}
+
+impl<'a> Ranking<'a> {
+    fn new(engine: &'a SwipeEngine, query: &'a SwipeQuery, previous_word: Option<&'a str>, limit: usize) -> Self {
+        let max_bigram_prob = previous_word.map_or(0.0, |previous| engine.max_bigram_probability(previous));
+        Self { engine, query, previous_word, limit, top: Vec::with_capacity(limit + 1), candidates: Vec::new(), max_bigram_prob, words: Vec::new() }
+    }
+
+    /// Distance above which a word with these penalties and frequency term can't enter the
+    /// best `limit`, so DTW can give up on it.
+    fn threshold(&self, penalty: f64, frequency_term: f64) -> f64 {
+        if self.top.len() < self.limit {
+            return f64::INFINITY;
+        }
+        match self.top.last() {
+            Some(kth) => (kth - frequency_term) * self.query.path.len() as f64 - penalty,
+            None => f64::NEG_INFINITY,
+        }
+    }
+
+    /// Penalties of word `word_idx` for the query, besides its distance.
+    fn penalty(&self, word_idx: usize) -> f64 {
+        let (engine, query) = (self.engine, self.query);
+        engine.start_penalty(word_idx, query.first_key.as_ref(), &query.first_pt, query.start_confidence)
+            + engine.end_penalty(word_idx, query.last_key.as_ref(), &query.last_pt, query.end_confidence)
+            + engine.key_hit_penalty(word_idx, &query.hits)
+            + engine.double_letter_penalty(word_idx, query.repeated_keys.as_deref())
+    }
+
+    /// Scores the words of path `path_id` besides its distance.
+    fn load_words(&mut self, path_id: usize) {
+        // the buffer is reused, since most paths have a single word
+        let mut words = std::mem::take(&mut self.words);
+        words.clear();
+        words.extend(self.engine.path_words(path_id).iter().map(|&word_idx| {
+            let (freq, bigram_prob) = self.engine.word_probabilities(&self.engine.dictionary.words[word_idx], self.previous_word);
+            (word_idx, self.penalty(word_idx), freq, bigram_prob)
+        }));
+        self.words = words;
+    }
+
+    /// Distance above which none of the loaded words can enter the best `limit`.
+    fn path_threshold(&self) -> f64 {
+        self.words
+            .iter()
+            .map(|&(_, penalty, freq, bigram_prob)| self.threshold(penalty, self.engine.combined_score(0.0, freq, bigram_prob)))
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    /// Adds the loaded words, whose path is at distance `dist`, if they can still enter the best `limit`.
+    fn add(&mut self, dist: f64) {
+        if dist == f64::INFINITY {
+            return;
+        }
+        for i in 0..self.words.len() {
+            let (word_idx, penalty, freq, bigram_prob) = self.words[i];
+            if dist > self.threshold(penalty, self.engine.combined_score(0.0, freq, bigram_prob)) {
+                continue;
+            }
+            let score = (dist + penalty) / self.query.path.len() as f64;
+            let combined = self.engine.combined_score(score, freq, bigram_prob);
+            self.top.insert(self.top.partition_point(|&s| s <= combined), combined);
+            self.top.truncate(self.limit);
+            self.candidates.push((self.engine.dictionary.words[word_idx].clone(), score, freq, bigram_prob));
+        }
+    }
+
+    fn into_predictions(self) -> Vec<Prediction> {
+        self.engine.sorted_predictions(self.candidates, self.limit)
+    }
+}
+
+impl TrieVisitor for Ranking<'_> {
+    fn branch_cutoff(&self, max_log_freq: f64) -> f64 {
+        // penalties are never negative
+        self.threshold(0.0, self.engine.combined_score(0.0, max_log_freq, self.max_bigram_prob))
+    }
+
+    fn visit(&mut self, path_id: usize, dist: f64) {
+        self.load_words(path_id);
+        self.add(dist);
+    }
+}
+
+/// Adds `word` to `dictionary` unless it is empty or already there. Returns whether it was added.
+fn add_word(dictionary: &mut Dictionary, word: &str, log_freq: f64) -> bool {
+    let lowercase = word.to_lowercase();
+    if word.is_empty() || dictionary.word_info.contains_key(&lowercase) {
+        return false;
+    }
+    dictionary.words.push(word.to_string());
+    dictionary.word_info.insert(lowercase, WordInfo { log_freq, count: 0 });
+    true
+}
+
+/// Checks that a model loaded for `lang_code` is in that language. Legacy models don't record one,
+/// so they take the requested language.
+fn resolve_language(header: &mut ModelHeader, lang_code: LanguageCode) -> Result<(), EngineLoadError> {
+    let expected = lang_code.to_string();
+    if header.language.is_empty() {
+        header.language = expected;
+    } else if header.language != expected {
+        return Err(EngineLoadError::LanguageMismatch { expected, found: header.language.clone() });
+    }
+    Ok(())
+}
+
+/// The built-in layout for the model's language, QWERTY for legacy models without one.
+fn default_layout(header: &ModelHeader) -> BuiltinLayout {
+    header.language.parse::<LanguageCode>().map(BuiltinLayout::for_language).unwrap_or(BuiltinLayout::Qwerty)
+}
+
+fn validate_layout(layout: &HashMap<char, Point>) -> Result<(), EngineLoadError> {
+    if layout.is_empty() {
+        return Err(EngineLoadError::InvalidLayout("layout has no keys".to_string()));
+    }
+    if let Some((key, point)) = layout.iter().find(|(_, point)| !point.x.is_finite() || !point.y.is_finite()) {
+        return Err(EngineLoadError::InvalidLayout(format!("key {:?} has non-finite position ({}, {})", key, point.x, point.y)));
+    }
+    Ok(())
+}
+
+/// The English engine. Panics if no English model can be loaded, so it is only available
+/// with a feature that provides models.
+#[cfg(any(feature = "download", feature = "embedded-models"))]
 impl Default for SwipeEngine {
     fn default() -> Self {
         Self::new(LanguageCode::En, None).unwrap()
@@ -220,8 +1014,778 @@ impl Default for SwipeEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use keyboard::{get_keyboard_layout, get_word_path};
+    use swipe_types::model::{encode_model, FrequencySource, MODEL_FORMAT_VERSION, MODEL_MAGIC};
+    use swipe_types::types::WordInfo;
+
+    pub(crate) fn test_dictionary() -> Dictionary {
+        let words = [
+            ("hello", 120), ("help", 80), ("hell", 20), ("held", 30), ("the", 500),
+            ("these", 90), ("there", 150), ("put", 60), ("please", 70), ("on", 300),
+            ("you", 400), ("yes", 100), ("can't", 50), ("cant", 5), ("because", 110),
+            ("to", 450), ("too", 200),
+        ];
+        let max_count = words.iter().map(|(_, count)| *count).max().unwrap() as f64;
+        let mut dictionary = Dictionary::new();
+        for (word, count) in words {
+            let log_freq = ((count as f64).ln() - 1.0) / max_count.ln();
+            dictionary.words.push(word.to_string());
+            dictionary.word_info.insert(word.to_string(), WordInfo { log_freq: log_freq.max(0.0), count });
+        }
+        dictionary
+    }
+
+    pub(crate) fn test_model_bytes() -> Vec<u8> {
+        encode_model("en", FrequencySource::Corpus, &test_dictionary())
+    }
+
+    #[test]
+    fn test_engine_from_bytes() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        assert_eq!(engine.word_count(), 17);
+
+        let predictions = engine.predict("hgrertyhjkllo", None, 3);
+        assert_eq!(predictions[0].word, "hello");
+    }
+
+    #[test]
+    fn test_engine_from_path() {
+        let model_path = std::env::temp_dir().join(format!("swipe-engine-test-{}.bin", std::process::id()));
+        fs::write(&model_path, test_model_bytes()).unwrap();
+        let engine = SwipeEngine::from_path(&model_path, None);
+        fs::remove_file(&model_path).unwrap();
+        assert_eq!(engine.unwrap().word_count(), 17);
+    }
+
+    #[test]
+    fn test_model_header() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        let mut header = engine.model_header().clone();
+        assert_eq!(header.format_version, MODEL_FORMAT_VERSION);
+        assert_eq!(header.language, "en");
+        assert_eq!(header.word_count, 17);
+        assert_eq!(header.frequency_source, FrequencySource::Corpus);
+        assert!(matches!(resolve_language(&mut header, LanguageCode::De), Err(EngineLoadError::LanguageMismatch { .. })));
+
+        // headerless models from older releases are migrated
+        let legacy = bincode::encode_to_vec(test_dictionary(), bincode::config::standard()).unwrap();
+        let engine = SwipeEngine::from_bytes(&legacy, None).unwrap();
+        assert_eq!(engine.model_header().format_version, 0);
+        assert_eq!(engine.word_count(), 17);
+        let mut header = engine.model_header().clone();
+        assert!(resolve_language(&mut header, LanguageCode::De).is_ok());
+        assert_eq!(header.language, "de");
+
+        let mut newer = MODEL_MAGIC.to_vec();
+        newer.extend(bincode::encode_to_vec(MODEL_FORMAT_VERSION + 1, bincode::config::standard()).unwrap());
+        assert!(matches!(
+            SwipeEngine::from_bytes(&newer, None),
+            Err(EngineLoadError::UnsupportedModelVersion { found, .. }) if found == MODEL_FORMAT_VERSION + 1
+        ));
+
+        let mut corrupted = test_model_bytes();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        assert!(matches!(SwipeEngine::from_bytes(&corrupted, None), Err(EngineLoadError::CorruptModel(_))));
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let missing = SwipeEngine::from_path(Path::new("does/not/exist.bin"), None);
+        assert!(matches!(missing, Err(EngineLoadError::Io { ref source, .. }) if source.kind() == std::io::ErrorKind::NotFound));
+        let error = missing.err().unwrap();
+        assert!(error.to_string().contains("does/not/exist.bin"));
+        assert!(std::error::Error::source(&error).is_some());
+
+        assert!(matches!(SwipeEngine::from_bytes(&[0xff, 0xff, 0xff], None), Err(EngineLoadError::Decode(_))));
+
+        let empty_layout = SwipeEngine::from_bytes(&test_model_bytes(), Some(HashMap::new()));
+        assert!(matches!(empty_layout, Err(EngineLoadError::InvalidLayout(_))));
+        let mut bad_layout = get_keyboard_layout();
+        bad_layout.insert('a', Point { x: f64::NAN, y: 0.0 });
+        assert!(matches!(SwipeEngine::from_bytes(&test_model_bytes(), Some(bad_layout)), Err(EngineLoadError::InvalidLayout(_))));
+    }
+
+    #[test]
+    fn test_builder_config() {
+        let config = EngineConfig { pop_weight: 0.4, end_penalty_factor: 0.3, ..EngineConfig::default() };
+        let engine = SwipeEngine::builder()
+            .model_bytes(test_model_bytes())
+            .config(config.clone())
+            .min_window(12)
+            .build()
+            .unwrap();
+        assert_eq!(engine.config(), &EngineConfig { min_window: 12, ..config });
+        assert_eq!(engine.predict("hgrertyhjkllo", None, 1)[0].word, "hello");
+
+        let stored = serde_json::to_string(engine.config()).unwrap();
+        let restored: EngineConfig = serde_json::from_str(&stored).unwrap();
+        assert_eq!(&restored, engine.config());
+        let partial: EngineConfig = serde_json::from_str(r#"{"pop_weight": 0.1}"#).unwrap();
+        assert_eq!(partial, EngineConfig { pop_weight: 0.1, ..EngineConfig::default() });
+
+        let invalid = SwipeEngine::builder().model_bytes(test_model_bytes()).resample_step(0.0).build();
+        assert!(matches!(invalid, Err(EngineLoadError::InvalidConfig(_))));
+        assert!(matches!(SwipeEngine::builder().build(), Err(EngineLoadError::InvalidConfig(_))));
+
+        let mut engine = engine;
+        engine.set_pop_weight(0.2).unwrap();
+        engine.set_bigram_weight(0.5).unwrap();
+        assert_eq!((engine.config().pop_weight, engine.config().bigram_weight), (0.2, 0.5));
+        assert!(matches!(engine.set_pop_weight(-1.0), Err(EngineLoadError::InvalidConfig(_))));
+        assert!(matches!(engine.set_bigram_weight(f64::NAN), Err(EngineLoadError::InvalidConfig(_))));
+        assert_eq!((engine.config().pop_weight, engine.config().bigram_weight), (0.2, 0.5));
+        assert_eq!(engine.predict("hgrertyhjkllo", None, 1)[0].word, "hello");
+    }
+
+    #[test]
+    fn test_predict_path() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        let layout = get_keyboard_layout();
+
+        // a curved, off-centre trace over h-e-l-l-o
+        let keys = ['h', 'e', 'l', 'o'];
+        let mut trace = Vec::new();
+        for pair in keys.windows(2) {
+            let (from, to) = (layout[&pair[0]], layout[&pair[1]]);
+            for s in 0..10 {
+                let t = s as f64 / 10.0;
+                let bulge = (t * std::f64::consts::PI).sin() * 0.3;
+                trace.push(Point { x: from.x + (to.x - from.x) * t + 0.15, y: from.y + (to.y - from.y) * t - bulge });
+            }
+        }
+        trace.push(Point { x: layout[&'o'].x + 0.1, y: layout[&'o'].y + 0.2 });
+
+        let predictions = engine.predict_path(&trace, None, 3);
+        assert_eq!(predictions[0].word, "hello");
+        assert!(engine.predict_path(&[], None, 3).is_empty());
+
+        let trace: SwipeTrace = trace.iter().enumerate().map(|(i, p)| TouchSample::new(p.x, p.y, i as f64 * 8.0)).collect();
+        let encoded = bincode::encode_to_vec(&trace, bincode::config::standard()).unwrap();
+        let (decoded, _): (SwipeTrace, usize) = bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        assert_eq!(decoded, trace);
+        assert_eq!(engine.predict_trace(&decoded, None, 1)[0].word, "hello");
+    }
+
+    #[test]
+    fn test_key_hits() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        let layout = get_keyboard_layout();
+
+        // moves at constant speed between keys and pauses briefly over the l
+        let mut trace = SwipeTrace::new();
+        let mut t_ms = 0.0;
+        for pair in ['h', 'e', 'l', 'p'].windows(2) {
+            let (from, to) = (layout[&pair[0]], layout[&pair[1]]);
+            for s in 0..12 {
+                let f = s as f64 / 12.0;
+                trace.push(TouchSample::new(from.x + (to.x - from.x) * f, from.y + (to.y - from.y) * f, t_ms));
+                t_ms += 10.0;
+            }
+            if pair[1] == 'l' {
+                for _ in 0..4 {
+                    trace.push(TouchSample::new(to.x + 0.02, to.y, t_ms));
+                    t_ms += 30.0;
+                }
+            }
+        }
+        trace.push(TouchSample::new(layout[&'p'].x, layout[&'p'].y, t_ms));
+
+        let hits = engine.key_hits(&trace);
+        assert_eq!(key_hits::key_string(&hits), "help");
+        assert_eq!(hits[0].kind, KeyHitKind::Start);
+        assert_eq!(hits[1].kind, KeyHitKind::Corner);
+        assert_eq!(hits[2].kind, KeyHitKind::Dwell);
+        assert_eq!(hits[3].kind, KeyHitKind::End);
+        assert_eq!((hits[0].confidence, hits[3].confidence), (1.0, 1.0));
+
+        let predictions = engine.predict_trace(&trace, None, 3);
+        assert_eq!(predictions[0].word, "help");
+
+        // starting just on the g side of the gap to h, words starting on h remain candidates
+        let (g, h) = (layout[&'g'], layout[&'h']);
+        let mut trace = SwipeTrace::new();
+        trace.push(TouchSample::new(g.x + (h.x - g.x) * 0.45, g.y, 0.0));
+        for (i, c) in "hello".chars().enumerate() {
+            trace.push(TouchSample::new(layout[&c].x, layout[&c].y, 100.0 * (i + 1) as f64));
+        }
+        let start = engine.key_hits(&trace)[0];
+        assert_eq!(start.key, 'g');
+        assert!(start.confidence < 0.2);
+        assert_eq!(engine.predict_trace(&trace, None, 1)[0].word, "hello");
+    }
+
+    #[test]
+    fn test_double_letters() {
+        let layout = get_keyboard_layout();
+        let hello: Vec<Point> = "hello".chars().map(|c| layout[&c]).collect();
+        assert_eq!(keyboard::repeated_keys(&hello), vec![layout[&'l']]);
+        assert_ne!(
+            keyboard::get_word_path_with_loops("too", &layout, 0.5, 0.25),
+            keyboard::get_word_path_with_loops("to", &layout, 0.5, 0.25)
+        );
+
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        let too = engine.predict("tyuioo", None, 2);
+        assert_eq!(too[0].word, "too");
+        assert_eq!(too[1].word, "to");
+        assert!(too[1].score - too[0].score > 0.1);
+        assert_eq!(engine.predict("tyuio", None, 1)[0].word, "to");
+
+        // a loop drawn on the key in a raw trace
+        let o = layout[&'o'];
+        let mut points = vec![layout[&'t'], o];
+        points.extend(keyboard::double_letter_loop(&o, 0.3, 0.1));
+        points.push(o);
+        assert_eq!(engine.predict_path(&points, None, 1)[0].word, "too");
+
+        // collapsing repeated keys leaves only the frequency to tell them apart
+        let engine = SwipeEngine::builder()
+            .model_bytes(test_model_bytes())
+            .double_letter_loop_radius(0.0)
+            .double_letter_penalty(0.0)
+            .build()
+            .unwrap();
+        let collapsed = engine.predict("tyuioo", None, 2);
+        assert_eq!(collapsed[0].word, "to");
+        assert_eq!(collapsed[0].score, collapsed[1].score);
+    }
+
+    #[test]
+    fn test_unknown_chars() {
+        // skipped characters leave the same path, the punctuated spelling wins on frequency
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        let predictions = engine.predict("cant", None, 2);
+        assert_eq!(predictions[0].word, "can't");
+        assert_eq!(predictions[1].word, "cant");
+        assert_eq!(predictions[0].score, predictions[1].score);
+
+        let alias = UnknownCharPolicy::Alias { keys: HashMap::from([('\'', 'l')]) };
+        let engine = SwipeEngine::builder().model_bytes(test_model_bytes()).unknown_chars(alias).build().unwrap();
+        assert_eq!(engine.predict("cant", None, 1)[0].word, "cant");
+        assert_eq!(engine.predict("can't", None, 1)[0].word, "can't");
+        assert_eq!(engine.predict("canlt", None, 1)[0].word, "can't");
+
+        let layer_switch = UnknownCharPolicy::LayerSwitch { key: Point { x: 0.0, y: 3.0 } };
+        let engine = SwipeEngine::builder().model_bytes(test_model_bytes()).unknown_chars(layer_switch).build().unwrap();
+        assert_eq!(engine.predict("cant", None, 1)[0].word, "cant");
+        assert_eq!(engine.predict("can't", None, 1)[0].word, "can't");
+
+        let missing_alias = UnknownCharPolicy::Alias { keys: HashMap::from([('-', '#')]) };
+        let result = SwipeEngine::builder().model_bytes(test_model_bytes()).unknown_chars(missing_alias).build();
+        assert!(matches!(result, Err(EngineLoadError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_folding() {
+        let mut layout = get_keyboard_layout();
+        assert_eq!(FoldingTable::for_language("da").fold("Ærø", &layout), "aeroe");
+        assert_eq!(FoldingTable::for_language("fr").fold("Élève", &layout), "eleve");
+        layout.insert('é', Point { x: 1.5, y: 3.0 });
+        assert_eq!(FoldingTable::for_language("fr").fold("Élève", &layout), "éleve");
+
+        let words = [("où", 50), ("ou", 100), ("été", 80), ("par", 40), ("élève", 30)];
+        let mut dictionary = Dictionary::new();
+        for (word, count) in words {
+            dictionary.words.push(word.to_string());
+            dictionary.word_info.insert(word.to_string(), WordInfo { log_freq: (count as f64).ln() / 100f64.ln(), count });
+        }
+        dictionary.pair_counts = Some(HashMap::from([("par".to_string(), HashMap::from([("où".to_string(), 30)]))]));
+        let bytes = encode_model("fr", FrequencySource::Corpus, &dictionary);
+
+        // 'é' is folded to 'e' unless the layout has a key for it
+        let engine = SwipeEngine::from_bytes(&bytes, Some(get_keyboard_layout())).unwrap();
+        assert_eq!(engine.predict("ete", None, 1)[0].word, "été");
+        assert_eq!(engine.predict("eleve", None, 1)[0].word, "élève");
+        assert_eq!(engine.predict("été", None, 1)[0].word, "été");
+
+        // accented forms sharing a path are ranked by frequency, then by bigram
+        let predictions = engine.predict("ou", None, 2);
+        assert_eq!(predictions[0].word, "ou");
+        assert_eq!(predictions[1].word, "où");
+        assert_eq!(predictions[0].score, predictions[1].score);
+        assert_eq!(engine.predict("ou", Some("par"), 1)[0].word, "où");
+
+        let engine = SwipeEngine::builder().model_bytes(bytes.clone()).layout(get_keyboard_layout()).folding(Folding::Disabled).build().unwrap();
+        assert!(engine.predict("ete", None, 5).iter().all(|p| p.word != "été"));
+
+        // the built-in AZERTY and QWERTZ only have keys for the letter rows
+        let engine = SwipeEngine::from_bytes(&bytes, None).unwrap();
+        assert_eq!(engine.layout, BuiltinLayout::Azerty.layout());
+        assert_eq!(engine.predict("ete", None, 1)[0].word, "été");
+        assert_eq!(engine.predict("été", None, 1)[0].word, "été");
+
+        let mut dictionary = test_dictionary();
+        add_word(&mut dictionary, "straße", 0.5);
+        let engine = SwipeEngine::from_bytes(&encode_model("de", FrequencySource::Corpus, &dictionary), None).unwrap();
+        assert_eq!(engine.layout, BuiltinLayout::Qwertz.layout());
+        assert_eq!(engine.predict("strasse", None, 1)[0].word, "straße");
+    }
+
+    #[test]
+    fn test_layout_file() {
+        let qwerty_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("layouts/qwerty.toml");
+        assert_eq!(keyboard::load_layout(&qwerty_path).unwrap(), get_keyboard_layout());
+
+        let json = r#"{
+            "name": "numbers",
+            "key_height": 0.5,
+            "rows": [
+                { "keys": [{ "label": "1" }, { "label": "2", "width": 2.0 }, { "label": "3" }] },
+                { "offset": 0.5, "keys": [{ "label": "A", "outputs": ["a", "b"] }, { "label": "0", "x": 3.0 }] }
+            ]
+        }"#;
+        let definition = KeyboardLayout::from_json_str(json).unwrap();
+        let layout = definition.to_layout().unwrap();
+        assert_eq!(layout[&'2'], Point { x: 1.5, y: 0.0 });
+        assert_eq!(layout[&'3'], Point { x: 3.0, y: 0.0 });
+        assert_eq!(layout[&'a'], Point { x: 0.5, y: 0.5 });
+        assert_eq!(layout[&'b'], layout[&'a']);
+        assert_eq!(layout[&'0'], Point { x: 3.0, y: 0.5 });
+        let engine = SwipeEngine::builder().model_bytes(test_model_bytes()).keyboard_layout(definition.clone()).build().unwrap();
+        assert_eq!(engine.layout, layout);
+
+        let mut overlapping = definition.clone();
+        overlapping.rows[1].keys[1].x = Some(0.0);
+        assert!(matches!(overlapping.to_layout(), Err(EngineLoadError::InvalidLayout(_))));
+        let mut duplicate = definition;
+        duplicate.rows[1].keys[0].outputs = vec!['1'];
+        assert!(matches!(duplicate.to_layout(), Err(EngineLoadError::InvalidLayout(_))));
+        assert!(KeyboardLayout::from_toml_str("name = 3").is_err());
+    }
+
+    #[test]
+    fn test_lower_bounds() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        for input in &engine.paths {
+            let window = engine.config.window(input.len());
+            let bounds = QueryBounds::new(input, window);
+            for (path, summary) in engine.paths.iter().zip(&engine.path_summaries) {
+                let dist = dtw_distance_fast(input, path, window, f64::INFINITY);
+                if bounds.length_mismatch(summary) {
+                    assert_eq!(dist, f64::INFINITY);
+                } else {
+                    assert!(bounds.lower_bound(summary, path, f64::INFINITY) <= dist + 1e-9);
+                }
+            }
+        }
+
+        // skipping words doesn't change the best predictions
+        let scored = |predictions: Vec<Prediction>| predictions.into_iter().map(|p| (p.word, p.score)).collect::<Vec<_>>();
+        for input in ["hgrertyhjkllo", "tgfdsresdftyuio", "yuio", "hgfdsdfgtyuio", "tyghe"] {
+            let query = engine.key_query(input).unwrap();
+            for limit in [1, 2, 5] {
+                let (top, stats) = engine.rank_candidates(&query, None, limit);
+                let (unpruned, unpruned_stats) = engine.rank_candidates_with(&query, None, limit, false);
+                assert_eq!(scored(top), scored(unpruned), "{} top {}", input, limit);
+                assert_eq!(stats.candidates, stats.pruned() + stats.distance_runs);
+                assert_eq!(unpruned_stats.pruned(), 0);
+            }
+        }
+        let (_, stats) = engine.predict_with_stats("tyghe", None, 1);
+        assert!(stats.pruned() > 0, "{:?}", stats);
+    }
 
     #[test]
+    fn test_shared_paths() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        // the apostrophe is skipped, so "can't" is typed like "cant"
+        assert_eq!(engine.path_count(), 16);
+        let cant = (0..engine.path_count()).map(|path_id| engine.path_words(path_id)).find(|words| words.len() > 1).unwrap();
+        assert_eq!(cant.iter().map(|&idx| engine.dictionary.words[idx].as_str()).collect::<Vec<_>>(), ["can't", "cant"]);
+        let predictions = engine.predict("cvbnmnbvcaertt", None, 2);
+        assert_eq!(predictions.iter().map(|p| p.word.as_str()).collect::<Vec<_>>(), ["can't", "cant"]);
+        assert_eq!(predictions[0].score, predictions[1].score);
+
+        // without loops, repeated keys collapse and each word keeps its own double letter penalty
+        for search in [SearchStrategy::Linear, SearchStrategy::Trie] {
+            let collapsed = SwipeEngine::builder().model_bytes(test_model_bytes()).double_letter_loop_radius(0.0).search(search).build().unwrap();
+            assert_eq!(collapsed.path_count(), 15);
+            let predictions = collapsed.predict("tyuio", None, 2);
+            assert_eq!(predictions.iter().map(|p| p.word.as_str()).collect::<Vec<_>>(), ["to", "too"]);
+            assert!(predictions[1].score > predictions[0].score);
+        }
+
+        // "these" and "there" only differ by one key, two key widths apart
+        let merged = SwipeEngine::builder().model_bytes(test_model_bytes()).template_tolerance(3.0).build().unwrap();
+        assert!((0..merged.path_count()).any(|path_id| merged.path_words(path_id) == [5, 6]));
+        assert_eq!(merged.predict("hgrertyhjkllo", None, 1)[0].word, "hello");
+        assert!(SwipeEngine::builder().model_bytes(test_model_bytes()).template_tolerance(-1.0).build().is_err());
+    }
+
+    #[test]
+    fn test_ann_retrieval() {
+        // the tree finds the same neighbours as a brute force search
+        let mut seed = 0x2545_f491u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let vectors: Vec<f64> = (0..500 * 4).map(|_| next()).collect();
+        let ids: Vec<usize> = (0..500).map(|i| i * 2).collect();
+        let tree = VpTree::new(&ids, &vectors, 4);
+        for q in 0..20 {
+            let query: Vec<f64> = (0..4).map(|_| next() + q as f64 * 0.01).collect();
+            let mut expected: Vec<(f64, usize)> = vectors
+                .chunks(4)
+                .zip(&ids)
+                .map(|(v, &id)| (v.iter().zip(&query).map(|(a, b)| (a - b) * (a - b)).sum::<f64>().sqrt(), id))
+                .collect();
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+            expected.truncate(10);
+            assert_eq!(tree.nearest(&query, 10), expected);
+        }
+
+        let exhaustive = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        let wide = SwipeEngine::builder().model_bytes(test_model_bytes()).retrieval(Retrieval::Ann { candidates: 100, points: 16 }).build().unwrap();
+        let narrow = SwipeEngine::builder().model_bytes(test_model_bytes()).retrieval(Retrieval::Ann { candidates: 2, points: 16 }).build().unwrap();
+        for input in ["hgrertyhjkllo", "tgfdsresdftyuio", "yuio", "tyghe", "hgfdsdfgtyuio"] {
+            let ranked = |predictions: Vec<Prediction>| predictions.into_iter().map(|p| (p.word, p.score)).collect::<Vec<_>>();
+            assert_eq!(ranked(wide.predict(input, None, 5)), ranked(exhaustive.predict(input, None, 5)));
+            let (_, stats) = narrow.predict_with_stats(input, None, 5);
+            assert!(stats.candidates <= 2, "{:?}", stats);
+        }
+        assert_eq!(narrow.predict("hgrertyhjkllo", None, 1)[0].word, "hello");
+        assert!(SwipeEngine::builder().model_bytes(test_model_bytes()).retrieval(Retrieval::Ann { candidates: 0, points: 16 }).build().is_err());
+    }
+
+    #[test]
+    fn test_trie_search() {
+        let ranked = |predictions: Vec<Prediction>| predictions.into_iter().map(|p| (p.word, p.score)).collect::<Vec<_>>();
+        let layout = get_keyboard_layout();
+        for key_model in [KeyModel::Centre, KeyModel::Gaussian { sigma: 0.5 }] {
+            let linear = SwipeEngine::builder().model_bytes(test_model_bytes()).key_model(key_model).build().unwrap();
+            let trie = SwipeEngine::builder().model_bytes(test_model_bytes()).key_model(key_model).search(SearchStrategy::Trie).build().unwrap();
+            assert!(!trie.tries.is_empty() && linear.tries.is_empty());
+
+            for input in ["hgrertyhjkllo", "tgfdsresdftyuio", "yuio", "tyghe", "hgfdsdfgtyuio", "tyhjuio", "cvbnmnbvcaertt"] {
+                for limit in [1, 3, 20] {
+                    let expected = linear.predict(input, Some("please"), limit);
+                    let (predictions, stats) = trie.predict_with_stats(input, Some("please"), limit);
+                    assert_eq!(ranked(predictions), ranked(expected), "{} with {:?}", input, key_model);
+                    assert_eq!(stats.candidates, stats.pruned() + stats.distance_runs);
+                }
+                let points: Vec<Point> = input.chars().map(|c| layout[&c]).collect();
+                assert_eq!(ranked(trie.predict_path(&points, None, 3)), ranked(linear.predict_path(&points, None, 3)));
+            }
+        }
+
+        // words that share a start with a better one are dropped without finishing their path
+        let trie = SwipeEngine::builder().model_bytes(test_model_bytes()).search(SearchStrategy::Trie).build().unwrap();
+        let (predictions, stats) = trie.predict_with_stats("tyuio", None, 1);
+        assert_eq!(predictions[0].word, "to");
+        assert!(stats.pruned_by_prefix > 0, "{:?}", stats);
+    }
+
+    #[test]
+    fn test_distance_metrics() {
+        let path = get_word_path("help", &get_keyboard_layout());
+        let shifted: Vec<Point> = path.iter().map(|p| Point { x: p.x + 0.3, y: p.y }).collect();
+        let window = path.len();
+        assert_eq!(Dtw.distance(&path, &path, window, f64::INFINITY), 0.0);
+        assert!(DerivativeDtw.distance(&path, &shifted, window, f64::INFINITY) < 1e-9);
+        let frechet = DiscreteFrechet.distance(&path, &shifted, window, f64::INFINITY);
+        assert!((frechet - 0.3 * path.len() as f64).abs() < 1e-9);
+        assert_eq!(DiscreteFrechet.distance(&path, &shifted, window, 0.1), f64::INFINITY);
+        let shape_only = Shark2 { location_weight: 0.0, ..Shark2::default() };
+        assert!(shape_only.distance(&path, &shifted, window, f64::INFINITY) < 1e-9);
+        assert!(Shark2::default().distance(&path, &shifted, window, f64::INFINITY) > 0.0);
+
+        let blend = DistanceMetric::Blend {
+            components: vec![
+                BlendComponent { metric: DistanceMetric::Dtw, weight: 1.0 },
+                BlendComponent { metric: DistanceMetric::Shark2(Shark2::default()), weight: 0.5 },
+            ],
+        };
+        for metric in [DistanceMetric::DerivativeDtw, DistanceMetric::Frechet, DistanceMetric::Shark2(Shark2::default()), blend.clone()] {
+            let engine = SwipeEngine::builder().model_bytes(test_model_bytes()).distance(metric.clone()).build().unwrap();
+            assert_eq!(engine.predict("hgrertyhjkllo", None, 1)[0].word, "hello", "{:?}", metric);
+            let mut session = engine.session(None);
+            for key in "hgrertyhjkllo".chars() {
+                session.push_key(key);
+            }
+            assert_eq!(session.finish(1)[0].word, "hello", "{:?}", metric);
+        }
+
+        let config = EngineConfig { distance: blend, ..EngineConfig::default() };
+        let restored: EngineConfig = serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(restored, config);
+        let empty = SwipeEngine::builder().model_bytes(test_model_bytes()).distance(DistanceMetric::Blend { components: vec![] }).build();
+        assert!(matches!(empty, Err(EngineLoadError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_start_radius() {
+        // the swipe starts on 'g' instead of 'h'
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        assert!(engine.predict("gello", None, 3).is_empty());
+
+        let engine = SwipeEngine::builder().model_bytes(test_model_bytes()).start_radius(1.0).build().unwrap();
+        let predictions = engine.predict("gello", None, 3);
+        assert_eq!(predictions[0].word, "hello");
+        let exact = engine.predict("hello", None, 1);
+        assert!(predictions[0].score > exact[0].score, "words starting on another key are penalized");
+
+        let layout = get_keyboard_layout();
+        let points: Vec<Point> = "gello".chars().map(|c| layout[&c]).collect();
+        assert_eq!(engine.predict_path(&points, None, 1)[0].word, "hello");
+        let mut session = engine.session(None);
+        for key in "gello".chars() {
+            session.push_key(key);
+        }
+        assert_eq!(session.finish(1)[0].word, "hello");
+    }
+
+    #[test]
+    fn test_layers() {
+        let toml = r##"
+            name = "letters and numbers"
+            rows = [
+                { keys = [{ label = "q" }, { label = "w" }, { label = "e" }, { label = "r" }, { label = "t" }, { label = "y" }, { label = "u" }, { label = "i" }, { label = "o" }, { label = "p" }] },
+                { offset = 0.5, keys = [{ label = "a" }, { label = "s" }, { label = "d" }, { label = "f" }, { label = "g" }, { label = "h" }, { label = "j" }, { label = "k" }, { label = "l" }] },
+                { offset = 1.5, keys = [{ label = "z" }, { label = "x" }, { label = "c" }, { label = "v" }, { label = "b" }, { label = "n" }, { label = "m" }] },
+                { keys = [{ label = "?123", switch_to = "numbers" }] },
+            ]
+
+            [[layers]]
+            name = "numbers"
+            rows = [
+                { keys = [{ label = "1" }, { label = "2" }, { label = "3" }, { label = "4" }, { label = "5" }, { label = "6" }, { label = "7" }, { label = "8" }, { label = "9" }, { label = "0" }] },
+                { offset = 0.5, keys = [{ label = "@" }, { label = "#" }, { label = "$" }, { label = "-" }, { label = "(" }, { label = ")" }, { label = ":" }] },
+                { keys = [] },
+                { keys = [{ label = "ABC", switch_to = "base" }] },
+            ]
+        "##;
+        let definition = KeyboardLayout::from_toml_str(toml).unwrap();
+        let engine = SwipeEngine::builder()
+            .model_bytes(test_model_bytes())
+            .keyboard_layout(definition.clone())
+            .extra_words(&["2nd", ":)"], -6.0)
+            .build()
+            .unwrap();
+        assert_eq!(engine.layer_names(), vec![BASE_LAYER, "numbers"]);
+        assert_eq!(engine.word_count(), 19);
+
+        // "2nd" switches to numbers and back to letters midway
+        let (keys, layer) = engine.layered_key_points("2nd", 0);
+        let switch = Point { x: 0.0, y: 3.0 };
+        assert_eq!(keys, vec![switch, Point { x: 1.0, y: 0.0 }, switch, engine.layout[&'n'], engine.layout[&'d']]);
+        assert_eq!(layer, 0);
+        assert_eq!(engine.predict("2nd", None, 1)[0].word, "2nd");
+        let mut session = engine.session(None);
+        for key in "2nd".chars() {
+            session.push_key(key);
+        }
+        assert_eq!(session.finish(1)[0].word, "2nd");
+
+        let trace: SwipeTrace = (0..=20).map(|i| TouchSample::new(3.0 - i as f64 * 0.1, 0.0, i as f64 * 10.0)).collect();
+        let predictions = engine.predict_trace_on_layer(&trace, "numbers", None, 5);
+        assert_eq!(predictions[0].word, "42");
+        // the key sequence gets a path score like the words and is ranked with them
+        assert!(predictions[0].score > 0.0 && predictions[0].score < predictions[1].score);
+        let combined: Vec<f64> = predictions.iter().map(|p| engine.combined_score(p.score, p.freq, p.bigram_prob.unwrap_or(0.0))).collect();
+        assert!(combined.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(engine.predict_trace_on_layer(&trace, "emoji", None, 5).is_empty());
+
+        let mut unknown_target = definition;
+        unknown_target.rows[3].keys[0].switch_to = Some("symbols".to_string());
+        assert!(matches!(unknown_target.layer_keys(), Err(EngineLoadError::InvalidLayout(_))));
+    }
+
+    #[test]
+    fn test_builtin_layouts() {
+        assert_eq!(BuiltinLayout::Qwerty.layout(), get_keyboard_layout());
+        for builtin in BuiltinLayout::ALL {
+            let layout = builtin.layout();
+            assert!(('a'..='z').all(|c| layout.contains_key(&c)), "{} is missing letters", builtin.name());
+            assert_eq!(builtin.name().parse::<BuiltinLayout>().unwrap(), builtin);
+        }
+
+        let qwertz = BuiltinLayout::Qwertz.layout();
+        assert!("üöä".chars().all(|c| qwertz.contains_key(&c)));
+        assert!(!qwertz.contains_key(&'ß'));
+        assert_eq!(qwertz[&'z'], Point { x: 5.0, y: 0.0 });
+        let azerty = BuiltinLayout::Azerty.layout();
+        assert!("éèçà".chars().all(|c| !azerty.contains_key(&c)));
+        assert_eq!(azerty[&'ù'], Point { x: 10.5, y: 1.0 });
+        assert!(azerty.values().all(|key| key.y >= 0.0));
+
+        assert_eq!(BuiltinLayout::for_language(LanguageCode::De), BuiltinLayout::Qwertz);
+        assert_eq!(BuiltinLayout::for_language(LanguageCode::Fr), BuiltinLayout::Azerty);
+        assert_eq!(BuiltinLayout::for_language(LanguageCode::En), BuiltinLayout::Qwerty);
+        let de_model = encode_model("de", FrequencySource::Corpus, &test_dictionary());
+        let engine = SwipeEngine::from_bytes(&de_model, None).unwrap();
+        assert_eq!(engine.layout, qwertz);
+        let engine = SwipeEngine::builder().model_bytes(test_model_bytes()).builtin_layout(BuiltinLayout::Colemak).build().unwrap();
+        assert_eq!(engine.layout, BuiltinLayout::Colemak.layout());
+        assert!("dvorak".parse::<BuiltinLayout>().is_ok() && "bepo".parse::<BuiltinLayout>().is_err());
+    }
+
+    #[test]
+    fn test_key_model() {
+        let layout = get_keyboard_layout();
+        let (input, word) = (keyboard::get_word_path("hgrertyhjkllo", &layout), keyboard::get_word_path("hello", &layout));
+        let banded = dtw::dtw_banded_with(input.len(), word.len(), 10, f64::INFINITY, |i, j| euclidean_dist(&input[i], &word[j]));
+        assert_eq!(banded, dtw_distance_fast(&input, &word, 10, f64::INFINITY));
+
+        // a touch strays further sideways on a wide key for the same cost
+        let gaussian = KeyModel::Gaussian { sigma: 0.5 };
+        let centre = Point { x: 0.0, y: 0.0 };
+        let touch = Point { x: 0.8, y: 0.0 };
+        assert!(gaussian.cost(&touch, &centre, 2.0, 1.0) < gaussian.cost(&touch, &centre, 1.0, 1.0));
+        assert_eq!(KeyModel::Centre.cost(&touch, &centre, 2.0, 1.0), 0.8);
+
+        let definition = KeyboardLayout::from_toml_str(r#"
+            name = "wide"
+            [[rows]]
+            keys = [{ label = "a" }, { label = "b", width = 4.0 }]
+        "#).unwrap();
+        // the tap is inside the wide key, but closer to the centre of the narrow one
+        let tap = Point { x: 1.0, y: 0.0 };
+        let rects = definition.key_rects().unwrap();
+        assert_eq!(rects[&'b'].centre, Point { x: 2.5, y: 0.0 });
+        assert_eq!(keyboard::most_likely_key(&tap, &rects, &KeyModel::Centre), Some('a'));
+        assert_eq!(keyboard::most_likely_key(&tap, &rects, &gaussian), Some('b'));
+
+        let engine = SwipeEngine::builder().model_bytes(test_model_bytes()).key_model(gaussian).build().unwrap();
+        assert_eq!(engine.correct_tap(&Point { x: 5.2, y: 1.1 }), Some('h'));
+        assert_eq!(engine.predict("hgrertyhjkllo", None, 1)[0].word, "hello");
+        let mut session = engine.session(None);
+        "hgrertyhjkllo".chars().for_each(|c| session.push_key(c));
+        assert_eq!(session.finish(1)[0].word, "hello");
+
+        let normalized = SwipeEngine::builder()
+            .model_bytes(test_model_bytes())
+            .key_model(gaussian)
+            .preprocessing(PathPreprocessing::Uniform { points: 32, normalize: true })
+            .build();
+        assert!(matches!(normalized, Err(EngineLoadError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_screen_mapping() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+        let layout = get_keyboard_layout();
+        let bounds = engine.layout_bounds();
+        assert_eq!(bounds, Bounds { min_x: -0.5, min_y: -0.5, max_x: 9.5, max_y: 2.5 });
+
+        // a phone keyboard of 1080x600 pixels at the bottom of the screen
+        let phone = engine.screen_mapping(Bounds::from_origin_size(0.0, 1800.0, 1080.0, 600.0));
+        assert_eq!(phone.regions[0].to_layout.inverse().apply(&layout[&'q']), Point { x: 54.0, y: 1900.0 });
+        let to_pixels = phone.regions[0].to_layout.inverse();
+        let pixels: Vec<Point> = "hello".chars().map(|c| to_pixels.apply(&layout[&c])).collect();
+        for (pixel, c) in pixels.iter().zip("hello".chars()) {
+            let mapped = phone.to_layout(pixel);
+            assert!(euclidean_dist(&mapped, &layout[&c]) < 1e-9);
+        }
+        assert_eq!(engine.predict_pixels(&pixels, &phone, None, 1)[0].word, "hello");
+
+        let trace: SwipeTrace = pixels.iter().enumerate().map(|(i, p)| TouchSample::new(p.x, p.y, i as f64 * 100.0)).collect();
+        assert_eq!(engine.predict_trace_pixels(&trace, &phone, None, 1)[0].word, "hello");
+
+        // a split tablet keyboard, the left half shows the keys up to t, g and b
+        let split = ScreenMapping::split(
+            Bounds::from_origin_size(0.0, 1000.0, 500.0, 300.0),
+            Bounds::from_origin_size(1500.0, 1000.0, 500.0, 300.0),
+            &bounds,
+            4.5,
+        );
+        assert!(split.validate().is_ok());
+        let e = split.to_layout(&Point { x: 250.0, y: 1050.0 });
+        let y = split.to_layout(&Point { x: 1550.0, y: 1050.0 });
+        assert_eq!(engine.correct_tap(&e), Some('e'));
+        assert_eq!(engine.correct_tap(&y), Some('y'));
+        assert!((y.x - 5.0).abs() < 1e-9);
+
+        // AZERTY has a longer middle row; a number row above the letters isn't part of the bounds
+        let mut dictionary = test_dictionary();
+        add_word(&mut dictionary, "été", 0.9);
+        let bytes = encode_model("fr", FrequencySource::Corpus, &dictionary);
+        let engine = SwipeEngine::from_bytes(&bytes, None).unwrap();
+        assert_eq!(engine.layout_bounds(), Bounds { min_x: -0.5, min_y: -0.5, max_x: 11.0, max_y: 2.5 });
+        let mut definition = BuiltinLayout::Azerty.definition();
+        definition.rows.insert(0, KeyboardLayout::from_toml_str(r#"
+            name = "digits"
+            [[rows]]
+            y = -1.0
+            keys = [{ label = "1" }, { label = "2" }, { label = "3" }]
+        "#).unwrap().rows.remove(0));
+        let with_numbers = SwipeEngine::builder().model_bytes(bytes).keyboard_layout(definition).build().unwrap();
+        assert_eq!(with_numbers.layout_bounds(), engine.layout_bounds());
+
+        let phone = engine.screen_mapping(Bounds::from_origin_size(0.0, 1800.0, 1150.0, 300.0));
+        let a = phone.to_layout(&Point { x: 50.0, y: 1850.0 });
+        assert!(euclidean_dist(&a, &engine.layout[&'a']) < 1e-9);
+        let pixels: Vec<Point> = "ete".chars().map(|c| phone.regions[0].to_layout.inverse().apply(&engine.layout[&c])).collect();
+        assert_eq!(pixels[0], Point { x: 250.0, y: 1850.0 });
+        assert_eq!(engine.predict_pixels(&pixels, &phone, None, 1)[0].word, "été");
+    }
+
+    #[test]
+    fn test_uniform_preprocessing() {
+        let path = keyboard::get_word_path("qwerty", &get_keyboard_layout());
+        let resampled = preprocess::resample_uniform(&path, 16);
+        assert_eq!(resampled.len(), 16);
+        let gaps: Vec<f64> = resampled.windows(2).map(|w| euclidean_dist(&w[0], &w[1])).collect();
+        assert!(gaps.iter().all(|gap| (gap - 5.0 / 15.0).abs() < 1e-9));
+
+        let normalized = preprocess::normalize_path(&resampled);
+        let width = normalized.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max) - normalized.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+        assert!((width - 1.0).abs() < 1e-9);
+
+        for normalize in [false, true] {
+            let engine = SwipeEngine::builder()
+                .model_bytes(test_model_bytes())
+                .preprocessing(PathPreprocessing::Uniform { points: 32, normalize })
+                .build()
+                .unwrap();
+            assert_eq!(engine.predict("hgrertyhjkllo", None, 1)[0].word, "hello");
+        }
+
+        // the shape channel ignores where the path was drawn and how large it is
+        let engine = SwipeEngine::builder()
+            .model_bytes(test_model_bytes())
+            .preprocessing(PathPreprocessing::Uniform { points: 32, normalize: true })
+            .build()
+            .unwrap();
+        let layout = get_keyboard_layout();
+        let start = layout[&'y'];
+        let trace: Vec<Point> = ['y', 'o', 'u'].iter().map(|c| Point { x: start.x + (layout[c].x - start.x) * 0.7 + 0.2, y: start.y + 0.1 }).collect();
+        assert_eq!(engine.predict_path(&trace, None, 1)[0].word, "you");
+    }
+
+    #[test]
+    fn test_session() {
+        let engine = SwipeEngine::from_bytes(&test_model_bytes(), None).unwrap();
+
+        let scored = |predictions: Vec<Prediction>| predictions.into_iter().map(|p| (p.word, p.score)).collect::<Vec<_>>();
+        let mut session = engine.session(None);
+        let mut keys = String::new();
+        for key in "hgrertyhjkllo".chars() {
+            session.push_key(key);
+            keys.push(key);
+            assert_eq!(scored(session.current_predictions(5)), scored(engine.predict(&keys, None, 5)), "{}", keys);
+        }
+        assert!(session.candidate_count() > 0);
+        assert_eq!(session.finish(1)[0].word, "hello");
+
+        // sessions use the same DTW band as predict, which widens on long swipes
+        let layout = get_keyboard_layout();
+        for swipe in ["hgrertyhjkllo", "tgfdsresdftyuio", "tgfdsresdftyuiokjhgfdsaqwertyuio"] {
+            let mut session = engine.session(None);
+            swipe.chars().for_each(|key| session.push_key(key));
+            assert_eq!(scored(session.finish(5)), scored(engine.predict(swipe, None, 5)), "{}", swipe);
+
+            // raw points give the same result as predict_path
+            let points: Vec<Point> = swipe.chars().map(|c| layout[&c]).collect();
+            let mut session = engine.session(None);
+            points.iter().for_each(|point| session.push_point(*point));
+            assert_eq!(scored(session.finish(5)), scored(engine.predict_path(&points, None, 5)), "{}", swipe);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "download", feature = "embedded-models"))]
     fn test_engine_creation() {
         let engine = SwipeEngine::new(LanguageCode::En, None).unwrap();
         assert!(engine.word_count() > 0, "Dictionary should be loaded by default");
@@ -229,6 +1793,16 @@ mod tests {
 
 
     #[test]
+    #[cfg(any(feature = "download", feature = "embedded-models"))]
+    fn test_folded_national_letters() {
+        let fr = SwipeEngine::new(LanguageCode::Fr, None).unwrap();
+        assert_eq!(fr.predict("ete", None, 3)[0].word, "été");
+        let de = SwipeEngine::new(LanguageCode::De, None).unwrap();
+        assert_eq!(de.predict("strasse", None, 3)[0].word, "straße");
+    }
+
+    #[test]
+    #[cfg(any(feature = "download", feature = "embedded-models"))]
     fn test_prediction() {
         let engine = SwipeEngine::new(LanguageCode::En, None).unwrap();
         println!("model loaded");
@@ -0,0 +1,97 @@
+use crate::keyboard::euclidean_dist;
+use serde::{Deserialize, Serialize};
+use swipe_types::types::Point;
+
+/// How input and word paths are prepared before they are compared.
+/// The same steps are applied to both, so their scores stay comparable.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum PathPreprocessing {
+    #[default]
+    /// Keeps a point every `resample_step` key units, so longer paths have more points.
+    Step,
+    /// Resamples every path to `points` equidistant points. With `normalize` the paths are
+    /// also centred on their centroid and scaled to a unit bounding box, which compares
+    /// only their shape (the SHARK2 shape channel) and ignores where on the keyboard they were drawn.
+    Uniform { points: usize, normalize: bool },
+}
+
+impl PathPreprocessing {
+    /// Applies the preprocessing to a path that was already simplified.
+    pub fn apply(&self, path: &[Point]) -> Vec<Point> {
+        match self {
+            Self::Step => path.to_vec(),
+            Self::Uniform { points, normalize } => {
+                let resampled = resample_uniform(path, *points);
+                if *normalize {
+                    normalize_path(&resampled)
+                } else {
+                    resampled
+                }
+            }
+        }
+    }
+}
+
+/// Resamples `path` to `n` points spaced equally along its arc length.
+/// A path without length is repeated `n` times.
+pub fn resample_uniform(path: &[Point], n: usize) -> Vec<Point> {
+    if path.is_empty() || n == 0 {
+        return vec![];
+    }
+    if n == 1 {
+        return vec![path[0]];
+    }
+
+    let total: f64 = path.windows(2).map(|w| euclidean_dist(&w[0], &w[1])).sum();
+    if total == 0.0 {
+        return vec![path[0]; n];
+    }
+
+    let interval = total / (n - 1) as f64;
+    let mut resampled = Vec::with_capacity(n);
+    resampled.push(path[0]);
+
+    let mut segment = 0;
+    let mut segment_start = 0.0;
+    for k in 1..n - 1 {
+        let target = interval * k as f64;
+        loop {
+            let seg_len = euclidean_dist(&path[segment], &path[segment + 1]);
+            if segment_start + seg_len >= target || segment + 2 == path.len() {
+                let t = if seg_len > 0.0 { ((target - segment_start) / seg_len).min(1.0) } else { 0.0 };
+                let (p1, p2) = (path[segment], path[segment + 1]);
+                resampled.push(Point { x: p1.x + (p2.x - p1.x) * t, y: p1.y + (p2.y - p1.y) * t });
+                break;
+            }
+            segment_start += seg_len;
+            segment += 1;
+        }
+    }
+    resampled.push(path[path.len() - 1]);
+    resampled
+}
+
+/// Moves the centroid of `path` to the origin and scales it so the larger side
+/// of its bounding box is 1. Paths without extent are only translated.
+pub fn normalize_path(path: &[Point]) -> Vec<Point> {
+    if path.is_empty() {
+        return vec![];
+    }
+
+    let n = path.len() as f64;
+    let cx = path.iter().map(|p| p.x).sum::<f64>() / n;
+    let cy = path.iter().map(|p| p.y).sum::<f64>() / n;
+
+    let (mut min_x, mut max_x, mut min_y, mut max_y) = (f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY);
+    for p in path {
+        min_x = min_x.min(p.x);
+        max_x = max_x.max(p.x);
+        min_y = min_y.min(p.y);
+        max_y = max_y.max(p.y);
+    }
+    let extent = (max_x - min_x).max(max_y - min_y);
+    let scale = if extent > 0.0 { 1.0 / extent } else { 1.0 };
+
+    path.iter().map(|p| Point { x: (p.x - cx) * scale, y: (p.y - cy) * scale }).collect()
+}
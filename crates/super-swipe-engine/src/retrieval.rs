@@ -0,0 +1,147 @@
+use crate::preprocess::resample_uniform;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use swipe_types::types::Point;
+
+/// How the engine gathers the word paths it scores for a swipe.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Retrieval {
+    /// Every path starting near the swipe.
+    #[default]
+    Exhaustive,
+    /// Only the `candidates` paths closest to the swipe once both are resampled to `points`
+    /// points (see [`embed_path`]), found with a vantage-point tree per start key. Much fewer
+    /// paths are scored, but the best word by distance can be missed. Doesn't apply to
+    /// [`crate::SwipeSession`], which follows every path as the swipe is drawn.
+    Ann { candidates: usize, points: usize },
+}
+
+impl Retrieval {
+    /// Checks that approximate retrieval returns candidates and resamples to at least 2 points.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::Ann { candidates, points } => {
+                if candidates == 0 {
+                    return Err("ann retrieval needs at least 1 candidate".to_string());
+                }
+                if points < 2 {
+                    return Err(format!("ann retrieval needs at least 2 points, got {}", points));
+                }
+                Ok(())
+            }
+            Self::Exhaustive => Ok(()),
+        }
+    }
+}
+
+/// Fixed-length embedding of a path: the coordinates of `points` points spaced equally along it,
+/// x and y interleaved. The Euclidean distance between two embeddings is a cheap stand-in for the
+/// distance between their paths.
+pub fn embed_path(path: &[Point], points: usize) -> Vec<f64> {
+    resample_uniform(path, points).iter().flat_map(|p| [p.x, p.y]).collect()
+}
+
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f64>().sqrt()
+}
+
+struct VpNode {
+    id: usize,
+    /// Median distance from this node's vector to the vectors below it.
+    radius: f64,
+    /// Subtree of the vectors within `radius`.
+    inside: Option<usize>,
+    /// Subtree of the vectors at `radius` or further.
+    outside: Option<usize>,
+}
+
+/// A vantage-point tree: each node splits the vectors below it by their distance to its own
+/// vector, so [`VpTree::nearest`] can skip the side a query is too far from.
+pub(crate) struct VpTree {
+    dims: usize,
+    nodes: Vec<VpNode>,
+    /// The vector of each node, `dims` values each, in node order.
+    vectors: Vec<f64>,
+}
+
+/// A neighbour found by [`VpTree::nearest`], ordered by distance.
+#[derive(PartialEq)]
+struct Neighbour(f64, usize);
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl VpTree {
+    /// Indexes `vectors`, `dims` values per item, identified by the matching entry of `ids`.
+    pub fn new(ids: &[usize], vectors: &[f64], dims: usize) -> Self {
+        let mut tree = Self { dims, nodes: Vec::with_capacity(ids.len()), vectors: Vec::with_capacity(vectors.len()) };
+        let mut order: Vec<usize> = (0..ids.len()).collect();
+        tree.build(&mut order, ids, vectors);
+        tree
+    }
+
+    fn build(&mut self, order: &mut [usize], ids: &[usize], vectors: &[f64]) -> Option<usize> {
+        let dims = self.dims;
+        let item = |i: usize| &vectors[i * dims..(i + 1) * dims];
+        let (&mut vantage, rest) = order.split_first_mut()?;
+        let node = self.nodes.len();
+        self.nodes.push(VpNode { id: ids[vantage], radius: 0.0, inside: None, outside: None });
+        self.vectors.extend_from_slice(item(vantage));
+        if rest.is_empty() {
+            return Some(node);
+        }
+
+        let mid = rest.len() / 2;
+        let centre = item(vantage);
+        rest.select_nth_unstable_by(mid, |&a, &b| distance(centre, item(a)).total_cmp(&distance(centre, item(b))));
+        let radius = distance(centre, item(rest[mid]));
+        let (inside, outside) = rest.split_at_mut(mid);
+        self.nodes[node].radius = radius;
+        self.nodes[node].inside = self.build(inside, ids, vectors);
+        self.nodes[node].outside = self.build(outside, ids, vectors);
+        Some(node)
+    }
+
+    /// The `k` items closest to `query`, closest first, with their distance.
+    pub fn nearest(&self, query: &[f64], k: usize) -> Vec<(f64, usize)> {
+        let mut best: BinaryHeap<Neighbour> = BinaryHeap::with_capacity(k + 1);
+        let farthest = |best: &BinaryHeap<Neighbour>| if best.len() < k { f64::INFINITY } else { best.peek().map_or(f64::INFINITY, |n| n.0) };
+        // nodes to visit, with a lower bound on the distance of any vector below them
+        let mut stack = if self.nodes.is_empty() || k == 0 { vec![] } else { vec![(0, 0.0)] };
+
+        while let Some((node_idx, bound)) = stack.pop() {
+            if bound > farthest(&best) {
+                continue;
+            }
+            let node = &self.nodes[node_idx];
+            let d = distance(query, &self.vectors[node_idx * self.dims..(node_idx + 1) * self.dims]);
+            if d < farthest(&best) {
+                best.push(Neighbour(d, node.id));
+                if best.len() > k {
+                    best.pop();
+                }
+            }
+
+            // by the triangle inequality, vectors inside are at least d - radius away and those outside radius - d
+            let inside = node.inside.map(|child| (child, (d - node.radius).max(0.0)));
+            let outside = node.outside.map(|child| (child, (node.radius - d).max(0.0)));
+            // the closer side is pushed last, so it is searched first
+            let (near, far) = if d < node.radius { (inside, outside) } else { (outside, inside) };
+            stack.extend(far.into_iter().chain(near));
+        }
+        best.into_sorted_vec().into_iter().map(|Neighbour(d, id)| (d, id)).collect()
+    }
+}
@@ -0,0 +1,160 @@
+use crate::keyboard::euclidean_dist;
+use serde::{Deserialize, Serialize};
+use std::ops::AddAssign;
+use swipe_types::types::Point;
+
+/// How the engine looks for the best words among the candidates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchStrategy {
+    /// Scores each path on its own, skipping the paths that lower bounds rule out.
+    #[default]
+    Linear,
+    /// Lays the word paths out along a prefix tree, so words that start with the same keys share
+    /// the DTW columns of their common start. Costs memory for the tree, and only applies to the
+    /// [`crate::DistanceMetric::Dtw`] metric with [`crate::Retrieval::Exhaustive`]; otherwise the
+    /// search is linear.
+    Trie,
+}
+
+/// How many candidates a prediction considered and how many it could skip.
+///
+/// Candidates are distinct word paths, shared by all the words typed the same way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Paths that start near the input, or those retrieved with [`crate::Retrieval::Ann`].
+    pub candidates: usize,
+    /// Paths skipped because they are too much shorter or longer than the input
+    /// to be aligned within the DTW band.
+    pub pruned_by_length: usize,
+    /// Paths skipped because a lower bound on their distance already ruled them out.
+    pub pruned_by_bound: usize,
+    /// Paths skipped with a whole branch of the [`SearchStrategy::Trie`].
+    pub pruned_by_prefix: usize,
+    /// Paths whose full distance was computed, including runs abandoned at the cutoff.
+    pub distance_runs: usize,
+}
+
+impl SearchStats {
+    pub fn pruned(&self) -> usize {
+        self.pruned_by_length + self.pruned_by_bound + self.pruned_by_prefix
+    }
+
+    /// Fraction of the candidates that were skipped without computing their distance.
+    pub fn reduction(&self) -> f64 {
+        if self.candidates == 0 {
+            return 0.0;
+        }
+        self.pruned() as f64 / self.candidates as f64
+    }
+}
+
+impl AddAssign for SearchStats {
+    fn add_assign(&mut self, other: Self) {
+        self.candidates += other.candidates;
+        self.pruned_by_length += other.pruned_by_length;
+        self.pruned_by_bound += other.pruned_by_bound;
+        self.pruned_by_prefix += other.pruned_by_prefix;
+        self.distance_runs += other.distance_runs;
+    }
+}
+
+/// What the lower bounds need to know about a word path, kept apart from the path
+/// so most candidates are ruled out without reading it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PathSummary {
+    pub len: usize,
+    pub first: Point,
+    pub last: Point,
+    /// Corners of the bounding box of the path.
+    pub min: Point,
+    pub max: Point,
+}
+
+impl PathSummary {
+    pub fn new(path: &[Point]) -> Self {
+        let origin = Point { x: 0.0, y: 0.0 };
+        let mut summary = Self {
+            len: path.len(),
+            first: path.first().copied().unwrap_or(origin),
+            last: path.last().copied().unwrap_or(origin),
+            min: Point { x: f64::INFINITY, y: f64::INFINITY },
+            max: Point { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY },
+        };
+        for p in path {
+            summary.min = Point { x: summary.min.x.min(p.x), y: summary.min.y.min(p.y) };
+            summary.max = Point { x: summary.max.x.max(p.x), y: summary.max.y.max(p.y) };
+        }
+        summary
+    }
+}
+
+/// Lower bounds on the banded DTW distance, with Euclidean point costs, between one input
+/// path and any word path.
+pub(crate) struct QueryBounds<'a> {
+    input: &'a [Point],
+    window: usize,
+    /// Bounding box of the input points within `window` of each word path index (LB_Keogh).
+    envelope: Vec<(Point, Point)>,
+}
+
+impl<'a> QueryBounds<'a> {
+    pub fn new(input: &'a [Point], window: usize) -> Self {
+        // word paths longer than this can't be aligned within the band
+        let max_len = input.len() + window;
+        let envelope = (0..max_len)
+            .map(|j| {
+                let lo = j.saturating_sub(window);
+                let hi = (j + window).min(input.len().saturating_sub(1));
+                let mut min = Point { x: f64::INFINITY, y: f64::INFINITY };
+                let mut max = Point { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY };
+                for p in input.get(lo..=hi).unwrap_or_default() {
+                    min = Point { x: min.x.min(p.x), y: min.y.min(p.y) };
+                    max = Point { x: max.x.max(p.x), y: max.y.max(p.y) };
+                }
+                (min, max)
+            })
+            .collect();
+        Self { input, window, envelope }
+    }
+
+    /// Whether the word path has too many or too few points to be aligned with the input.
+    /// Paths are resampled at a fixed step, so this compares their lengths.
+    pub fn length_mismatch(&self, word: &PathSummary) -> bool {
+        word.len == 0 || self.input.is_empty() || self.input.len().abs_diff(word.len) > self.window
+    }
+
+    /// The largest of the endpoint and LB_Keogh bounds, returned as soon as one exceeds `threshold`.
+    pub fn lower_bound(&self, word: &PathSummary, word_path: &[Point], threshold: f64) -> f64 {
+        let (Some(input_first), Some(input_last)) = (self.input.first(), self.input.last()) else {
+            return f64::INFINITY;
+        };
+
+        // every alignment starts with both first points and ends with both last points
+        let mut bound = euclidean_dist(input_first, &word.first);
+        if self.input.len() > 1 || word.len > 1 {
+            bound += euclidean_dist(input_last, &word.last);
+        }
+        if bound > threshold {
+            return bound;
+        }
+
+        // every input point is aligned with at least one point of the word path
+        let keogh_input: f64 = self.input.iter().map(|p| box_distance(p, &word.min, &word.max)).sum();
+        bound = bound.max(keogh_input);
+        if bound > threshold {
+            return bound;
+        }
+
+        // and every word path point with at least one input point within the band
+        let keogh_word: f64 = word_path.iter().zip(&self.envelope).map(|(p, (min, max))| box_distance(p, min, max)).sum();
+        bound.max(keogh_word)
+    }
+}
+
+/// Distance from `point` to the box between `min` and `max`, 0 inside.
+fn box_distance(point: &Point, min: &Point, max: &Point) -> f64 {
+    let dx = (min.x - point.x).max(point.x - max.x).max(0.0);
+    let dy = (min.y - point.y).max(point.y - max.y).max(0.0);
+    dx.hypot(dy)
+}
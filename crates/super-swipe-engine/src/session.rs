@@ -0,0 +1,304 @@
+use crate::distance::DistanceMetric;
+use crate::keyboard::{double_letter_loop, euclidean_dist, repeated_keys};
+use crate::preprocess::PathPreprocessing;
+use crate::SwipeEngine;
+use std::collections::HashMap;
+use swipe_types::types::{Point, Prediction, TouchSample};
+
+/// DTW state of one word path, shared by the words typed along it.
+struct Candidate {
+    path_id: usize,
+    /// Last DTW row: the cost of aligning the input so far with each prefix of the word path.
+    row: Vec<f64>,
+}
+
+/// Predicts a word while the swipe is still in progress.
+///
+/// Points or keys are pushed as they arrive. Each candidate path keeps its last DTW row,
+/// so an update only computes the row for the new input points. The rows span the whole word
+/// path rather than the band [`SwipeEngine::predict`] uses, whose width depends on the final
+/// input length, so their last cell bounds the banded distance from below. Predictions are
+/// rescored with the band in order of that bound, until no other word can enter the best ones,
+/// which gives the scores of [`SwipeEngine::predict`].
+///
+/// Candidates whose cheapest partial alignment falls too far behind the best one, by
+/// [`crate::EngineConfig::session_prune_margin`] per input point, are dropped as the swipe goes
+/// on. This pruning is a heuristic: a dropped word can occasionally be one `predict` would return.
+///
+/// Incremental updates need [`PathPreprocessing::Step`] and the [`DistanceMetric::Dtw`] metric.
+/// Otherwise the session rescores the whole input on every call to [`SwipeSession::current_predictions`].
+pub struct SwipeSession<'a> {
+    engine: &'a SwipeEngine,
+    previous_word: Option<String>,
+    /// Raw input, kept to rescore when the input can't be processed incrementally.
+    raw_points: Vec<Point>,
+    keys: String,
+    /// Whether raw points were pushed, so the keys don't describe the whole input.
+    points_pushed: bool,
+    /// Input path as compared with the word paths.
+    path: Vec<Point>,
+    /// Distance travelled since the last resampled point.
+    carried: f64,
+    /// Key of the first input, if it was pushed as a key.
+    first_key: Option<Point>,
+    /// Key of the last input, if it was pushed as a key.
+    last_key: Option<Point>,
+    /// Layer the last pushed key is on.
+    layer: usize,
+    candidates: Option<Vec<Candidate>>,
+}
+
+impl<'a> SwipeSession<'a> {
+    pub(crate) fn new(engine: &'a SwipeEngine, previous_word: Option<&str>) -> Self {
+        Self {
+            engine,
+            previous_word: previous_word.map(str::to_string),
+            raw_points: Vec::new(),
+            keys: String::new(),
+            points_pushed: false,
+            path: Vec::new(),
+            carried: 0.0,
+            first_key: None,
+            last_key: None,
+            layer: 0,
+            candidates: None,
+        }
+    }
+
+    fn incremental(&self) -> bool {
+        self.engine.config.preprocessing == PathPreprocessing::Step && self.engine.config.distance == DistanceMetric::Dtw
+    }
+
+    /// Adds a raw touch point, in the same coordinate space as the layout.
+    pub fn push_point(&mut self, point: Point) {
+        let previous = self.raw_points.last().cloned();
+        self.raw_points.push(point);
+        self.points_pushed = true;
+        self.last_key = None;
+        if !self.incremental() {
+            return;
+        }
+
+        let Some(previous) = previous else {
+            self.extend_path(&[point]);
+            return;
+        };
+
+        // resample the new segment the same way as SwipeEngine::predict_path
+        let step_size = self.engine.config.resample_step;
+        let mut new_points = Vec::new();
+        let mut seg_start = previous;
+        let mut seg_len = euclidean_dist(&seg_start, &point);
+        while self.carried + seg_len >= step_size && seg_len > 0.0 {
+            let t = (step_size - self.carried) / seg_len;
+            let new_point = Point {
+                x: seg_start.x + (point.x - seg_start.x) * t,
+                y: seg_start.y + (point.y - seg_start.y) * t,
+            };
+            new_points.push(new_point);
+            seg_start = new_point;
+            seg_len = euclidean_dist(&seg_start, &point);
+            self.carried = 0.0;
+        }
+        self.carried += seg_len;
+        self.extend_path(&new_points);
+    }
+
+    pub fn push_sample(&mut self, sample: TouchSample) {
+        self.push_point(sample.point());
+    }
+
+    /// Adds the next key the swipe passed through. Accented letters are folded, keys on other
+    /// layers are reached through their switch keys, and other characters that aren't on the
+    /// layout follow the engine's [`crate::UnknownCharPolicy`].
+    pub fn push_key(&mut self, key: char) {
+        let (key_points, layer) = self.engine.layered_key_points(&key.to_string(), self.layer);
+        if key_points.is_empty() {
+            return;
+        }
+        self.keys.push(key);
+        self.layer = layer;
+        for key_point in key_points {
+            self.push_key_point(key_point);
+        }
+    }
+
+    fn push_key_point(&mut self, key_point: Point) {
+        let previous = self.raw_points.last().cloned();
+        if previous.is_none() {
+            self.first_key = Some(key_point);
+        }
+        self.raw_points.push(key_point);
+        self.last_key = Some(key_point);
+        if !self.incremental() {
+            return;
+        }
+
+        // interpolate between keys the same way as get_word_path
+        let step_size = self.engine.config.resample_step;
+        let mut new_points = Vec::new();
+        if let Some(previous) = previous {
+            let dist = euclidean_dist(&previous, &key_point);
+            if dist == 0.0 {
+                new_points.extend(double_letter_loop(&key_point, self.engine.config.double_letter_loop_radius, step_size));
+            } else if dist > step_size {
+                let num_steps = (dist / step_size) as i32;
+                let dx = (key_point.x - previous.x) / num_steps as f64;
+                let dy = (key_point.y - previous.y) / num_steps as f64;
+                for s in 1..num_steps {
+                    new_points.push(Point { x: previous.x + dx * s as f64, y: previous.y + dy * s as f64 });
+                }
+            }
+        }
+        new_points.push(key_point);
+        self.extend_path(&new_points);
+    }
+
+    /// Appends points to the input path, dropping near-duplicates, and advances the DTW rows.
+    fn extend_path(&mut self, points: &[Point]) {
+        for &point in points {
+            if let Some(last) = self.path.last() {
+                if euclidean_dist(&point, last) <= self.engine.config.simplify_threshold {
+                    continue;
+                }
+            }
+            self.path.push(point);
+            if self.candidates.is_none() {
+                self.candidates = Some(self.initial_candidates(&point));
+            }
+            self.advance(&point);
+        }
+    }
+
+    fn initial_candidates(&self, first_point: &Point) -> Vec<Candidate> {
+        self.engine
+            .start_candidates(first_point, self.engine.config.start_radius)
+            .into_iter()
+            .map(|path_id| {
+                let mut row = vec![f64::INFINITY; self.engine.paths[path_id].len() + 1];
+                row[0] = 0.0;
+                Candidate { path_id, row }
+            })
+            .collect()
+    }
+
+    /// Computes the DTW row of `point` for every candidate and prunes hopeless ones.
+    fn advance(&mut self, point: &Point) {
+        let engine = self.engine;
+        let Some(candidates) = self.candidates.as_mut() else {
+            return;
+        };
+
+        let mut best_row_min = f64::INFINITY;
+        let mut row_mins = Vec::with_capacity(candidates.len());
+        for candidate in candidates.iter_mut() {
+            let mut diagonal = candidate.row[0];
+            candidate.row[0] = f64::INFINITY;
+            let mut row_min = f64::INFINITY;
+            for j in 1..candidate.row.len() {
+                let cost = engine.point_cost(point, candidate.path_id, j - 1);
+                let above = candidate.row[j];
+                candidate.row[j] = cost + above.min(candidate.row[j - 1]).min(diagonal);
+                diagonal = above;
+                row_min = row_min.min(candidate.row[j]);
+            }
+            best_row_min = best_row_min.min(row_min);
+            row_mins.push(row_min);
+        }
+
+        // the row minimum only grows as input is added, so it bounds each candidate's final cost
+        // from below; comparing it with the best candidate's bound rather than its cost is what
+        // makes the pruning approximate
+        let margin = engine.config.session_prune_margin * self.path.len() as f64;
+        let mut row_mins = row_mins.into_iter();
+        candidates.retain(|_| row_mins.next().unwrap() <= best_row_min + margin);
+    }
+
+    /// Best predictions for the input received so far.
+    pub fn current_predictions(&self, limit: usize) -> Vec<Prediction> {
+        if !self.incremental() {
+            return self.rescore(limit);
+        }
+        let (Some(candidates), Some(first_pt), Some(last_pt)) = (&self.candidates, self.raw_points.first(), self.raw_points.last()) else {
+            return vec![];
+        };
+
+        if limit == 0 {
+            return vec![];
+        }
+
+        let engine = self.engine;
+        let input_len = self.path.len() as f64;
+        let input_repeated = self.keys_only().then(|| repeated_keys(&self.raw_points));
+        // each word with the lower bound of its path's distance, its penalty, log frequency and bigram probability
+        let mut bounded: Vec<(usize, usize, f64, f64, f64, f64)> = candidates
+            .iter()
+            .filter(|candidate| candidate.row.last().unwrap().is_finite())
+            .flat_map(|candidate| {
+                let bound = *candidate.row.last().unwrap();
+                engine.path_words(candidate.path_id).iter().map(move |&word_idx| (candidate.path_id, word_idx, bound))
+            })
+            .map(|(path_id, word_idx, bound)| {
+                let penalty = engine.start_penalty(word_idx, self.first_key.as_ref(), first_pt, 1.0)
+                    + engine.end_penalty(word_idx, self.last_key.as_ref(), last_pt, 1.0)
+                    + engine.double_letter_penalty(word_idx, input_repeated.as_deref());
+                let (freq, bigram_prob) = engine.word_probabilities(&engine.dictionary.words[word_idx], self.previous_word.as_deref());
+                (path_id, word_idx, bound, penalty, freq, bigram_prob)
+            })
+            .collect();
+        let combined = |dist: f64, penalty: f64, freq: f64, bigram_prob: f64| engine.combined_score((dist + penalty) / input_len, freq, bigram_prob);
+        bounded.sort_by(|a, b| combined(a.2, a.3, a.4, a.5).total_cmp(&combined(b.2, b.3, b.4, b.5)));
+
+        // rescore with the band until the bound of the next word is worse than the best `limit`
+        let window = engine.config.window(self.path.len());
+        let mut distances: HashMap<usize, f64> = HashMap::new();
+        let mut top: Vec<f64> = Vec::with_capacity(limit + 1);
+        let mut scored = Vec::new();
+        for (path_id, word_idx, bound, penalty, freq, bigram_prob) in bounded {
+            if top.len() == limit && combined(bound, penalty, freq, bigram_prob) > top[limit - 1] {
+                break;
+            }
+            let dist = *distances.entry(path_id).or_insert_with(|| engine.path_distance(&self.path, path_id, window, f64::INFINITY));
+            if !dist.is_finite() {
+                continue;
+            }
+            let score = combined(dist, penalty, freq, bigram_prob);
+            top.insert(top.partition_point(|&s| s <= score), score);
+            top.truncate(limit);
+            scored.push((word_idx, (dist + penalty) / input_len, freq, bigram_prob));
+        }
+        // in dictionary order, like predict, so ties are broken the same way
+        scored.sort_by_key(|&(word_idx, ..)| word_idx);
+        let results = scored.into_iter().map(|(word_idx, score, freq, bigram_prob)| (engine.dictionary.words[word_idx].clone(), score, freq, bigram_prob)).collect();
+        engine.sorted_predictions(results, limit)
+    }
+
+    fn rescore(&self, limit: usize) -> Vec<Prediction> {
+        if self.keys_only() {
+            self.engine.predict(&self.keys, self.previous_word.as_deref(), limit)
+        } else {
+            self.engine.predict_path(&self.raw_points, self.previous_word.as_deref(), limit)
+        }
+    }
+
+    /// Whether only keys were pushed so far.
+    fn keys_only(&self) -> bool {
+        !self.keys.is_empty() && !self.points_pushed
+    }
+
+    /// Ends the swipe and returns the final predictions.
+    pub fn finish(mut self, limit: usize) -> Vec<Prediction> {
+        // the trace ends exactly on its last point, like SwipeEngine::predict_path
+        if self.incremental() && self.last_key.is_none() && self.carried > 0.0 {
+            if let Some(&last) = self.raw_points.last() {
+                self.extend_path(&[last]);
+            }
+        }
+        self.current_predictions(limit)
+    }
+
+    /// Number of words still being tracked.
+    pub fn candidate_count(&self) -> usize {
+        self.candidates.as_ref().map_or(0, |candidates| candidates.iter().map(|c| self.engine.path_words(c.path_id).len()).sum())
+    }
+}
@@ -0,0 +1,283 @@
+use crate::EngineLoadError;
+use codes_iso_639::part_1::LanguageCode;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// URL template of the models published alongside the crate.
+pub const DEFAULT_MODEL_URL: &str =
+    "https://raw.githubusercontent.com/oneshinyboi/swipeType/{version}/crates/super-swipe-engine/assets/{lang}.bin";
+/// Release tag the default models are pinned to.
+pub const DEFAULT_MODEL_VERSION: &str = "v0.1.10";
+
+#[derive(Clone, Debug)]
+pub enum ModelLocation {
+    /// URL template, `{version}` and `{lang}` are replaced with the model version and 639-1 code.
+    /// Fetching from a URL requires the `download` feature.
+    Url(String),
+    /// Directory containing `{lang}.bin` files, such as the crate's `assets/` directory.
+    Directory(PathBuf),
+}
+
+/// Describes where language models come from and how they are verified.
+#[derive(Clone, Debug)]
+pub struct ModelSource {
+    pub location: ModelLocation,
+    pub version: String,
+    /// Download cache directory, defaults to the cached-path cache dir.
+    pub cache_dir: Option<PathBuf>,
+    /// Expected SHA-256 of each model as hex, keyed by 639-1 code.
+    pub checksums: HashMap<String, String>,
+}
+
+impl ModelSource {
+    pub fn url(template: &str) -> Self {
+        Self {
+            location: ModelLocation::Url(template.to_string()),
+            ..Self::default()
+        }
+    }
+
+    pub fn directory(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            location: ModelLocation::Directory(dir.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    pub fn with_checksum(mut self, lang_code: LanguageCode, sha256_hex: &str) -> Self {
+        self.checksums.insert(lang_code.to_string(), sha256_hex.to_ascii_lowercase());
+        self
+    }
+
+    /// Returns the URL the model for `lang_code` is fetched from, if this is a URL source.
+    pub fn model_url(&self, lang_code: LanguageCode) -> Option<String> {
+        match &self.location {
+            ModelLocation::Url(template) => Some(
+                template
+                    .replace("{version}", &self.version)
+                    .replace("{lang}", lang_code.as_ref()),
+            ),
+            ModelLocation::Directory(_) => None,
+        }
+    }
+
+    /// Returns a local path to the model for `lang_code`, downloading it into the cache if needed.
+    pub fn model_path(&self, lang_code: LanguageCode) -> Result<PathBuf, EngineLoadError> {
+        match &self.location {
+            ModelLocation::Directory(dir) => Ok(dir.join(format!("{}.bin", lang_code))),
+            ModelLocation::Url(_) => self.download(lang_code),
+        }
+    }
+
+    #[cfg(feature = "download")]
+    fn download(&self, lang_code: LanguageCode) -> Result<PathBuf, EngineLoadError> {
+        let url = self.model_url(lang_code).unwrap();
+        let result = match &self.cache_dir {
+            Some(cache_dir) => cached_path::Cache::builder()
+                .dir(cache_dir.clone())
+                .build()
+                .and_then(|cache| cache.cached_path(&url)),
+            None => cached_path::cached_path(&url),
+        };
+        result.map_err(|e| match e {
+            cached_path::Error::HttpError(ref http) if http.status().is_some_and(|status| status.as_u16() == 404) => {
+                EngineLoadError::UnsupportedLanguage(lang_code)
+            }
+            e => EngineLoadError::Network { url, source: Box::new(e) },
+        })
+    }
+
+    #[cfg(not(feature = "download"))]
+    fn download(&self, lang_code: LanguageCode) -> Result<PathBuf, EngineLoadError> {
+        Err(EngineLoadError::NoModelSource(lang_code))
+    }
+
+    /// Reads the model for `lang_code` and checks it against the expected checksum, if one is set.
+    /// A language without a model file in the directory or on the mirror is unsupported.
+    pub fn load(&self, lang_code: LanguageCode) -> Result<Vec<u8>, EngineLoadError> {
+        let path = self.model_path(lang_code)?;
+        let bytes = fs::read(&path).map_err(|source| match source.kind() {
+            io::ErrorKind::NotFound => EngineLoadError::UnsupportedLanguage(lang_code),
+            _ => EngineLoadError::Io { path: path.clone(), source },
+        })?;
+
+        if let Some(expected) = self.checksums.get(&lang_code.to_string()) {
+            let found = sha256_hex(&bytes);
+            if !found.eq_ignore_ascii_case(expected) {
+                return Err(EngineLoadError::ChecksumMismatch { path, expected: expected.clone(), found });
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+impl Default for ModelSource {
+    fn default() -> Self {
+        Self {
+            location: ModelLocation::Url(DEFAULT_MODEL_URL.to_string()),
+            version: DEFAULT_MODEL_VERSION.to_string(),
+            cache_dir: None,
+            checksums: HashMap::new(),
+        }
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_dictionary, test_model_bytes};
+    use crate::{BuiltinLayout, SwipeEngine};
+    use swipe_types::types::WordInfo;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("swipe-engine-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_directory_source_checksum() {
+        let dir = temp_dir("source-dir");
+        let bytes = test_model_bytes();
+        fs::write(dir.join("en.bin"), &bytes).unwrap();
+
+        let source = ModelSource::directory(&dir).with_checksum(LanguageCode::En, &sha256_hex(&bytes));
+        let engine = SwipeEngine::from_source(LanguageCode::En, &source, None).unwrap();
+        assert_eq!(engine.word_count(), 17);
+        let missing = SwipeEngine::from_source(LanguageCode::Fr, &source, None);
+        assert!(matches!(missing, Err(EngineLoadError::UnsupportedLanguage(LanguageCode::Fr))));
+
+        // a truncated file must be refused before it is decoded
+        fs::write(dir.join("en.bin"), &bytes[..bytes.len() / 2]).unwrap();
+        let result = SwipeEngine::from_source(LanguageCode::En, &source, None);
+        assert!(matches!(result, Err(EngineLoadError::ChecksumMismatch { .. })));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_model_language() {
+        // headerless models don't record their language, so the requested one picks the layout
+        let dir = temp_dir("legacy-de");
+        let legacy = bincode::encode_to_vec(test_dictionary(), bincode::config::standard()).unwrap();
+        fs::write(dir.join("de.bin"), &legacy).unwrap();
+
+        let engine = SwipeEngine::from_source(LanguageCode::De, &ModelSource::directory(&dir), None).unwrap();
+        assert_eq!(engine.model_header().format_version, 0);
+        assert_eq!(engine.model_header().language, "de");
+        assert_eq!(engine.layout, BuiltinLayout::Qwertz.layout());
+        assert!(engine.layout.contains_key(&'ü'));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_model_folding() {
+        // the requested language also picks the folding table, which spells 'þ' out as "th"
+        let dir = temp_dir("legacy-is");
+        let mut dictionary = test_dictionary();
+        dictionary.words.push("þetta".to_string());
+        dictionary.word_info.insert("þetta".to_string(), WordInfo { log_freq: 0.9, count: 0 });
+        let legacy = bincode::encode_to_vec(dictionary, bincode::config::standard()).unwrap();
+        fs::write(dir.join("is.bin"), &legacy).unwrap();
+
+        let engine = SwipeEngine::from_source(LanguageCode::Is, &ModelSource::directory(&dir), None).unwrap();
+        assert_eq!(engine.predict("thetta", None, 1)[0].word, "þetta");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_model_url() {
+        let source = ModelSource::url("https://mirror.example/models/{version}/{lang}.bin").with_version("v2");
+        assert_eq!(source.model_url(LanguageCode::De).unwrap(), "https://mirror.example/models/v2/de.bin");
+        assert!(ModelSource::directory("assets").model_url(LanguageCode::De).is_none());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "download", feature = "embedded-models")))]
+    fn test_no_model_source() {
+        let result = SwipeEngine::new(LanguageCode::En, None);
+        assert!(matches!(result, Err(EngineLoadError::NoModelSource(LanguageCode::En))));
+        assert!(result.err().unwrap().to_string().contains("`download` feature"));
+    }
+
+    #[test]
+    #[cfg(feature = "download")]
+    fn test_url_source_from_local_mirror() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let assets_dir = temp_dir("mirror-assets");
+        let bytes = test_model_bytes();
+        fs::write(assets_dir.join("en.bin"), &bytes).unwrap();
+
+        // minimal stand-in for a static file server hosting the assets/ directory
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let served_dir = assets_dir.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or_default();
+                let file_name = parts.next().unwrap_or_default().rsplit('/').next().unwrap_or_default();
+                match fs::read(served_dir.join(file_name)) {
+                    Ok(body) => {
+                        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"{}\"\r\nConnection: close\r\n\r\n", body.len(), sha256_hex(&body)).unwrap();
+                        if method != "HEAD" {
+                            stream.write_all(&body).unwrap();
+                        }
+                    }
+                    Err(_) => write!(stream, "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap(),
+                }
+            }
+        });
+
+        let cache_dir = temp_dir("mirror-cache");
+        let source = ModelSource::url(&format!("http://127.0.0.1:{}/{{version}}/{{lang}}.bin", port))
+            .with_version("v0.0.0")
+            .with_cache_dir(&cache_dir)
+            .with_checksum(LanguageCode::En, &sha256_hex(&bytes));
+        let engine = SwipeEngine::from_source(LanguageCode::En, &source, None).unwrap();
+        assert_eq!(engine.word_count(), 17);
+
+        let tampered = source.with_checksum(LanguageCode::En, &sha256_hex(b"something else"));
+        assert!(matches!(
+            SwipeEngine::from_source(LanguageCode::En, &tampered, None),
+            Err(EngineLoadError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            SwipeEngine::from_source(LanguageCode::Fr, &tampered, None),
+            Err(EngineLoadError::UnsupportedLanguage(LanguageCode::Fr))
+        ));
+
+        fs::remove_dir_all(&assets_dir).unwrap();
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use crate::keyboard::KeyRect;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use swipe_types::types::{Point, SwipeTrace, TouchSample};
+
+/// An axis-aligned rectangle, in screen pixels or layout key units.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl Bounds {
+    /// Bounds of a rectangle given by its top left corner and size, as screen coordinates usually are.
+    pub fn from_origin_size(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self { min_x: x, min_y: y, max_x: x + width, max_y: y + height }
+    }
+
+    /// Smallest bounds containing all key rectangles.
+    pub fn of_keys(keys: &HashMap<char, KeyRect>) -> Self {
+        let mut bounds = Self { min_x: f64::INFINITY, min_y: f64::INFINITY, max_x: f64::NEG_INFINITY, max_y: f64::NEG_INFINITY };
+        for rect in keys.values() {
+            bounds.min_x = bounds.min_x.min(rect.centre.x - rect.width / 2.0);
+            bounds.max_x = bounds.max_x.max(rect.centre.x + rect.width / 2.0);
+            bounds.min_y = bounds.min_y.min(rect.centre.y - rect.height / 2.0);
+            bounds.max_y = bounds.max_y.max(rect.centre.y + rect.height / 2.0);
+        }
+        bounds
+    }
+
+    /// Smallest bounds containing the letter keys, or all keys if none is a letter. Number rows
+    /// and punctuation keys around the letters are left out.
+    pub fn of_letter_keys(keys: &HashMap<char, KeyRect>) -> Self {
+        let letters: HashMap<char, KeyRect> = keys.iter().filter(|(c, _)| c.is_alphabetic()).map(|(c, rect)| (*c, *rect)).collect();
+        Self::of_keys(if letters.is_empty() { keys } else { &letters })
+    }
+
+    pub fn width(&self) -> f64 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max_y - self.min_y
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        (self.min_x..=self.max_x).contains(&point.x) && (self.min_y..=self.max_y).contains(&point.y)
+    }
+
+    /// Distance from `point` to the closest point of the bounds, 0 inside.
+    pub fn distance(&self, point: &Point) -> f64 {
+        let dx = (self.min_x - point.x).max(point.x - self.max_x).max(0.0);
+        let dy = (self.min_y - point.y).max(point.y - self.max_y).max(0.0);
+        dx.hypot(dy)
+    }
+}
+
+/// Scales and translates points from one space to another, independently on each axis,
+/// so keyboards of any aspect ratio map onto the layout.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayoutTransform {
+    pub scale_x: f64,
+    pub scale_y: f64,
+    pub offset_x: f64,
+    pub offset_y: f64,
+}
+
+impl LayoutTransform {
+    pub const IDENTITY: Self = Self { scale_x: 1.0, scale_y: 1.0, offset_x: 0.0, offset_y: 0.0 };
+
+    /// Maps `from` onto `to`, corner to corner.
+    pub fn fit(from: &Bounds, to: &Bounds) -> Self {
+        let scale_x = to.width() / from.width();
+        let scale_y = to.height() / from.height();
+        Self { scale_x, scale_y, offset_x: to.min_x - from.min_x * scale_x, offset_y: to.min_y - from.min_y * scale_y }
+    }
+
+    pub fn apply(&self, point: &Point) -> Point {
+        Point { x: point.x * self.scale_x + self.offset_x, y: point.y * self.scale_y + self.offset_y }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            scale_x: 1.0 / self.scale_x,
+            scale_y: 1.0 / self.scale_y,
+            offset_x: -self.offset_x / self.scale_x,
+            offset_y: -self.offset_y / self.scale_y,
+        }
+    }
+}
+
+/// A part of the screen and the transform from its pixels to layout space.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScreenRegion {
+    pub screen: Bounds,
+    pub to_layout: LayoutTransform,
+}
+
+/// Maps touch points in screen pixels into layout space.
+///
+/// A plain keyboard has a single region. A split keyboard has one region per half,
+/// each showing a part of the layout.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScreenMapping {
+    pub regions: Vec<ScreenRegion>,
+}
+
+impl ScreenMapping {
+    /// Stretches the whole layout over the keyboard's `screen` bounds.
+    pub fn fit(screen: Bounds, layout: &Bounds) -> Self {
+        Self { regions: vec![ScreenRegion { screen, to_layout: LayoutTransform::fit(&screen, layout) }] }
+    }
+
+    /// Shows the part of the layout left of `split_x` in `left` and the rest in `right`.
+    pub fn split(left: Bounds, right: Bounds, layout: &Bounds, split_x: f64) -> Self {
+        let left_layout = Bounds { max_x: split_x, ..*layout };
+        let right_layout = Bounds { min_x: split_x, ..*layout };
+        Self {
+            regions: vec![
+                ScreenRegion { screen: left, to_layout: LayoutTransform::fit(&left, &left_layout) },
+                ScreenRegion { screen: right, to_layout: LayoutTransform::fit(&right, &right_layout) },
+            ],
+        }
+    }
+
+    /// Maps a pixel to layout space with the region it is in, or the closest one
+    /// for touches that leave the keyboard.
+    pub fn to_layout(&self, pixel: &Point) -> Point {
+        let region = self
+            .regions
+            .iter()
+            .min_by(|a, b| a.screen.distance(pixel).partial_cmp(&b.screen.distance(pixel)).unwrap_or(std::cmp::Ordering::Equal));
+        match region {
+            Some(region) => region.to_layout.apply(pixel),
+            None => *pixel,
+        }
+    }
+
+    pub fn map_points(&self, pixels: &[Point]) -> Vec<Point> {
+        pixels.iter().map(|p| self.to_layout(p)).collect()
+    }
+
+    /// Maps the samples of a trace, keeping their timestamps and pressure.
+    pub fn map_trace(&self, trace: &SwipeTrace) -> SwipeTrace {
+        trace
+            .samples
+            .iter()
+            .map(|sample| {
+                let point = self.to_layout(&sample.point());
+                TouchSample { x: point.x, y: point.y, ..*sample }
+            })
+            .collect()
+    }
+
+    /// Checks that every region has a finite, non-empty area and scale.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.regions.is_empty() {
+            return Err("screen mapping has no regions".to_string());
+        }
+        for region in &self.regions {
+            let t = region.to_layout;
+            if !(region.screen.width() > 0.0 && region.screen.height() > 0.0) {
+                return Err(format!("screen region {:?} is empty", region.screen));
+            }
+            if ![t.scale_x, t.scale_y, t.offset_x, t.offset_y].iter().all(|v| v.is_finite()) || t.scale_x == 0.0 || t.scale_y == 0.0 {
+                return Err(format!("screen region {:?} has an invalid transform", region.screen));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use swipe_types::types::Point;
+
+/// Receives the paths reached by [`PathTrie::search`] and decides which branches are worth following.
+pub(crate) trait TrieVisitor {
+    /// Largest distance still worth computing in a branch whose most frequent word has `max_log_freq`.
+    fn branch_cutoff(&self, max_log_freq: f64) -> f64;
+    fn visit(&mut self, path_id: usize, dist: f64);
+}
+
+struct TrieNode {
+    point: Point,
+    /// A path that goes through this node, and the index of the node's point in that path.
+    template: (usize, usize),
+    children: Vec<usize>,
+    /// Paths that end here.
+    paths: Vec<usize>,
+    /// Number of paths that end here or below.
+    subtree_paths: usize,
+    max_log_freq: f64,
+}
+
+/// Word paths laid out along a prefix tree, one template point per node.
+///
+/// Words typed with the same first keys share the start of their path, so [`PathTrie::search`]
+/// computes each DTW column once per node rather than once per path.
+pub(crate) struct PathTrie {
+    /// The root, without a point, comes first.
+    nodes: Vec<TrieNode>,
+}
+
+impl PathTrie {
+    pub fn new() -> Self {
+        let root = TrieNode {
+            point: Point { x: 0.0, y: 0.0 },
+            template: (0, 0),
+            children: vec![],
+            paths: vec![],
+            subtree_paths: 0,
+            max_log_freq: f64::NEG_INFINITY,
+        };
+        Self { nodes: vec![root] }
+    }
+
+    /// Adds path `path_id`, which must not be empty, with the highest log frequency of its words.
+    pub fn insert(&mut self, path_id: usize, path: &[Point], log_freq: f64) {
+        let mut node = 0;
+        self.nodes[0].subtree_paths += 1;
+        self.nodes[0].max_log_freq = self.nodes[0].max_log_freq.max(log_freq);
+        for (j, point) in path.iter().enumerate() {
+            let existing = self.nodes[node].children.iter().copied().find(|&child| self.nodes[child].point == *point);
+            node = match existing {
+                Some(child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TrieNode {
+                        point: *point,
+                        template: (path_id, j),
+                        children: vec![],
+                        paths: vec![],
+                        subtree_paths: 0,
+                        max_log_freq: f64::NEG_INFINITY,
+                    });
+                    self.nodes[node].children.push(child);
+                    child
+                }
+            };
+            self.nodes[node].subtree_paths += 1;
+            self.nodes[node].max_log_freq = self.nodes[node].max_log_freq.max(log_freq);
+        }
+        self.nodes[node].paths.push(path_id);
+    }
+
+    /// Number of paths in the trie.
+    pub fn path_count(&self) -> usize {
+        self.nodes[0].subtree_paths
+    }
+
+    /// Banded DTW of an input of `n` points against every path, computed column by column
+    /// down the trie. `cost(i, path_id, j)` is the cost of aligning input point `i` with point `j`
+    /// of path `path_id`. Branches whose cheapest column cell is above the visitor's cutoff
+    /// are skipped, since a column's minimum bounds the distance of every path below it.
+    /// Returns the number of paths skipped.
+    pub fn search<C, V>(&self, n: usize, window: usize, cost: C, visitor: &mut V) -> usize
+    where
+        C: Fn(usize, usize, usize) -> f64,
+        V: TrieVisitor,
+    {
+        let mut columns: Vec<Vec<f64>> = vec![vec![f64::INFINITY; n + 1]];
+        columns[0][0] = 0.0;
+        let mut stack: Vec<(usize, usize)> = self.nodes[0].children.iter().rev().map(|&child| (child, 1)).collect();
+        let mut pruned = 0;
+
+        while let Some((node_idx, depth)) = stack.pop() {
+            let node = &self.nodes[node_idx];
+            if columns.len() <= depth {
+                columns.push(vec![f64::INFINITY; n + 1]);
+            }
+            // the parent's column is still at depth - 1, since the trie is walked depth first
+            let (parents, rest) = columns.split_at_mut(depth);
+            let (prev, column) = (&parents[depth - 1], &mut rest[0]);
+
+            column.fill(f64::INFINITY);
+            let (path_id, j) = node.template;
+            let mut column_min = f64::INFINITY;
+            for i in depth.saturating_sub(window).max(1)..=(depth + window).min(n) {
+                let prev_min = prev[i].min(column[i - 1]).min(prev[i - 1]);
+                column[i] = cost(i - 1, path_id, j) + prev_min;
+                column_min = column_min.min(column[i]);
+            }
+
+            // columns past the band are all infinite
+            if column_min == f64::INFINITY || column_min > visitor.branch_cutoff(node.max_log_freq) {
+                pruned += node.subtree_paths;
+                continue;
+            }
+            for &path_id in &node.paths {
+                visitor.visit(path_id, column[n]);
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, depth + 1)));
+        }
+        pruned
+    }
+}
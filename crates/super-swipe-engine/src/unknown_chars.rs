@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use swipe_types::types::Point;
+
+/// How characters that have no key on the layout, such as apostrophes, hyphens
+/// and digits, contribute to word paths and key inputs.
+///
+/// Words are always returned as they are spelled in the dictionary, so "can't"
+/// is scored on its letters and still predicted with its apostrophe.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum UnknownCharPolicy {
+    /// The character is left out of the path.
+    #[default]
+    Skip,
+    /// The character is typed with the key it maps to, for example `'-' => 'p'` on a layout
+    /// with a long-press symbol. Characters without an alias are left out.
+    Alias { keys: HashMap<char, char> },
+    /// The path passes through `key`, for example the symbol layer switch, for every
+    /// character that isn't on the layout.
+    LayerSwitch { key: Point },
+}
+
+impl UnknownCharPolicy {
+    /// Centres of the keys the path of `word` passes through, in order.
+    pub fn key_points(&self, word: &str, layout: &HashMap<char, Point>) -> Vec<Point> {
+        word.chars()
+            .filter_map(|c| {
+                let c = c.to_ascii_lowercase();
+                if let Some(point) = layout.get(&c) {
+                    return Some(*point);
+                }
+                match self {
+                    Self::Skip => None,
+                    Self::Alias { keys } => keys.get(&c).and_then(|alias| layout.get(&alias.to_ascii_lowercase())).cloned(),
+                    Self::LayerSwitch { key } => Some(*key),
+                }
+            })
+            .collect()
+    }
+
+    /// Checks that aliases point to keys on `layout` and that the switch key has a finite position.
+    pub fn validate(&self, layout: &HashMap<char, Point>) -> Result<(), String> {
+        match self {
+            Self::Skip => Ok(()),
+            Self::Alias { keys } => match keys.iter().find(|(_, alias)| !layout.contains_key(&alias.to_ascii_lowercase())) {
+                Some((c, alias)) => Err(format!("alias {:?} of {:?} is not on the layout", alias, c)),
+                None => Ok(()),
+            },
+            Self::LayerSwitch { key } if !key.x.is_finite() || !key.y.is_finite() => {
+                Err(format!("layer switch key has non-finite position ({}, {})", key.x, key.y))
+            }
+            Self::LayerSwitch { .. } => Ok(()),
+        }
+    }
+}
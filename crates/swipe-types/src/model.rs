@@ -0,0 +1,133 @@
+use crate::types::Dictionary;
+use bincode::{config, Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// First bytes of every versioned model file.
+pub const MODEL_MAGIC: [u8; 4] = *b"SWPM";
+/// Format version written by this crate. Files without a header are treated as version 0.
+pub const MODEL_FORMAT_VERSION: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum FrequencySource {
+    /// Counts were read from a `word_freq.txt` file.
+    WordFrequency,
+    /// Counts were gathered from a `corpus.txt` file.
+    Corpus,
+    /// Legacy files don't record how they were built.
+    Unknown,
+}
+
+/// Describes the dictionary stored in a model file.
+///
+/// On disk a model is [`MODEL_MAGIC`], the bincode encoded header and then the
+/// bincode encoded [`Dictionary`], whose checksum is stored in the header.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct ModelHeader {
+    pub format_version: u16,
+    /// 639-1 code of the dictionary language, empty for legacy files.
+    pub language: String,
+    /// Whether the model was built with bigram (`use-pair-counts`) data.
+    pub pair_counts: bool,
+    pub frequency_source: FrequencySource,
+    pub word_count: u64,
+    /// FNV-1a hash of the encoded dictionary.
+    pub checksum: u64,
+}
+
+#[derive(Debug)]
+pub enum ModelFormatError {
+    /// The file was written by a newer, incompatible format version.
+    UnsupportedVersion { found: u16, supported: u16 },
+    /// The dictionary doesn't match the checksum recorded in the header.
+    ChecksumMismatch { expected: u64, found: u64 },
+    /// The dictionary holds a different number of words than the header states.
+    WordCountMismatch { expected: u64, found: u64 },
+    Decode(bincode::error::DecodeError),
+}
+
+impl fmt::Display for ModelFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion { found, supported } => write!(f, "model format version {} is not supported, expected at most {}", found, supported),
+            Self::ChecksumMismatch { expected, found } => write!(f, "model checksum {:016x} does not match header checksum {:016x}", found, expected),
+            Self::WordCountMismatch { expected, found } => write!(f, "model contains {} words but its header states {}", found, expected),
+            Self::Decode(e) => write!(f, "failed to decode model: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ModelFormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl ModelHeader {
+    fn legacy(dictionary: &Dictionary) -> Self {
+        Self {
+            format_version: 0,
+            language: String::new(),
+            pair_counts: dictionary.pair_counts.is_some(),
+            frequency_source: FrequencySource::Unknown,
+            word_count: dictionary.words.len() as u64,
+            checksum: 0,
+        }
+    }
+}
+
+/// Serializes `dictionary` with a versioned header.
+pub fn encode_model(language: &str, frequency_source: FrequencySource, dictionary: &Dictionary) -> Vec<u8> {
+    let payload = bincode::encode_to_vec(dictionary, config::standard()).unwrap();
+    let header = ModelHeader {
+        format_version: MODEL_FORMAT_VERSION,
+        language: language.to_string(),
+        pair_counts: dictionary.pair_counts.is_some(),
+        frequency_source,
+        word_count: dictionary.words.len() as u64,
+        checksum: fnv1a_64(&payload),
+    };
+
+    let mut bytes = MODEL_MAGIC.to_vec();
+    bytes.extend(bincode::encode_to_vec(&header, config::standard()).unwrap());
+    bytes.extend(payload);
+    bytes
+}
+
+/// Reads a model file, migrating legacy headerless files to a version 0 header.
+pub fn decode_model(bytes: &[u8]) -> Result<(ModelHeader, Dictionary), ModelFormatError> {
+    let Some(rest) = bytes.strip_prefix(&MODEL_MAGIC) else {
+        let (dictionary, _len) = bincode::decode_from_slice::<Dictionary, _>(bytes, config::standard())
+            .map_err(ModelFormatError::Decode)?;
+        return Ok((ModelHeader::legacy(&dictionary), dictionary));
+    };
+
+    // the version leads the header so it can be checked before the rest is decoded
+    let (format_version, _len) = bincode::decode_from_slice::<u16, _>(rest, config::standard())
+        .map_err(ModelFormatError::Decode)?;
+    if format_version > MODEL_FORMAT_VERSION {
+        return Err(ModelFormatError::UnsupportedVersion { found: format_version, supported: MODEL_FORMAT_VERSION });
+    }
+
+    let (header, header_len) = bincode::decode_from_slice::<ModelHeader, _>(rest, config::standard())
+        .map_err(ModelFormatError::Decode)?;
+    let payload = &rest[header_len..];
+    let checksum = fnv1a_64(payload);
+    if checksum != header.checksum {
+        return Err(ModelFormatError::ChecksumMismatch { expected: header.checksum, found: checksum });
+    }
+
+    let (dictionary, _len) = bincode::decode_from_slice::<Dictionary, _>(payload, config::standard())
+        .map_err(ModelFormatError::Decode)?;
+    if dictionary.words.len() as u64 != header.word_count {
+        return Err(ModelFormatError::WordCountMismatch { expected: header.word_count, found: dictionary.words.len() as u64 });
+    }
+    Ok((header, dictionary))
+}
+
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
@@ -2,12 +2,87 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use bincode::{Decode, Encode};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
+/// A single touch event of a swipe, in the same coordinate space as the keyboard layout.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Encode, Decode)]
+pub struct TouchSample {
+    pub x: f64,
+    pub y: f64,
+    /// Time since the start of the swipe, in milliseconds.
+    pub t_ms: f64,
+    pub pressure: Option<f32>,
+}
+
+impl TouchSample {
+    pub fn new(x: f64, y: f64, t_ms: f64) -> Self {
+        Self { x, y, t_ms, pressure: None }
+    }
+
+    pub fn point(&self) -> Point {
+        Point { x: self.x, y: self.y }
+    }
+}
+
+impl From<TouchSample> for Point {
+    fn from(sample: TouchSample) -> Self {
+        sample.point()
+    }
+}
+
+/// The touch samples of one swipe gesture, ordered by time.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Encode, Decode)]
+pub struct SwipeTrace {
+    pub samples: Vec<TouchSample>,
+}
+
+impl SwipeTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, sample: TouchSample) {
+        self.samples.push(sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Time between the first and last sample, in milliseconds.
+    pub fn duration_ms(&self) -> f64 {
+        match (self.samples.first(), self.samples.last()) {
+            (Some(first), Some(last)) => last.t_ms - first.t_ms,
+            _ => 0.0,
+        }
+    }
+
+    /// The sample positions without timing information.
+    pub fn points(&self) -> Vec<Point> {
+        self.samples.iter().map(TouchSample::point).collect()
+    }
+}
+
+impl From<Vec<TouchSample>> for SwipeTrace {
+    fn from(samples: Vec<TouchSample>) -> Self {
+        Self { samples }
+    }
+}
+
+impl FromIterator<TouchSample> for SwipeTrace {
+    fn from_iter<I: IntoIterator<Item = TouchSample>>(iter: I) -> Self {
+        Self { samples: iter.into_iter().collect() }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Prediction {
     pub word: String,